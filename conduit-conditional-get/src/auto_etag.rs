@@ -0,0 +1,293 @@
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+use conduit::{box_error, header, Body, Method, RequestExt, StatusCode};
+use conduit_middleware::{AfterResult, Middleware};
+
+use crate::hash::{content_hash, read_content_hash};
+
+/// How `AutoEtag` handles `Body::File` and `Body::Stream` bodies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnbufferedBodies {
+    /// Don't add an `ETag` to them.
+    Skip,
+    /// Hash bodies of up to `max_len` bytes.
+    ///
+    /// Files are read and rewound, while streams are buffered in memory and
+    /// replaced by a `Body::Owned`. Longer streams are passed on unchanged.
+    Hash { max_len: u64 },
+}
+
+impl Default for UnbufferedBodies {
+    fn default() -> UnbufferedBodies {
+        UnbufferedBodies::Skip
+    }
+}
+
+/// Adds an `ETag` derived from the body to `200 OK` responses to `GET` and
+/// `HEAD` requests that don't have one.
+///
+/// A router answers `HEAD` requests for routes without a `HEAD` handler with
+/// the `GET` handler, and removes the body of its response before the
+/// middleware around the router runs. Add this middleware inside the
+/// router, such as with `Route::middleware` or `Group::middleware` of
+/// `conduit-router`, so that it still sees the body to hash.
+///
+/// To let `ConditionalGet` answer with `304 Not Modified` when the tag
+/// matches, add this middleware after it, so that it runs first:
+///
+/// ```
+/// # use conduit::{Body, RequestExt, Response};
+/// # use conduit_conditional_get::{AutoEtag, ConditionalGet};
+/// # use conduit_middleware::MiddlewareBuilder;
+/// # fn handler(_: &mut dyn RequestExt) -> conduit::HttpResult {
+/// #     Response::builder().body(Body::from_static(b"{}"))
+/// # }
+/// let mut stack = MiddlewareBuilder::new(handler);
+/// stack.add(ConditionalGet);
+/// stack.add(AutoEtag::new());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct AutoEtag {
+    weak: bool,
+    unbuffered: UnbufferedBodies,
+}
+
+impl AutoEtag {
+    pub fn new() -> AutoEtag {
+        AutoEtag::default()
+    }
+
+    /// Send weak tags, such as `W/"1f2e"`, for bodies that are only
+    /// semantically equivalent between encodings or minor changes.
+    pub fn weak(&mut self, weak: bool) -> &mut AutoEtag {
+        self.weak = weak;
+        self
+    }
+
+    /// Set how bodies that aren't in memory are handled, see
+    /// `UnbufferedBodies`.
+    pub fn unbuffered(&mut self, unbuffered: UnbufferedBodies) -> &mut AutoEtag {
+        self.unbuffered = unbuffered;
+        self
+    }
+
+    /// The hash of the body, if it can be computed.
+    fn hash(&self, body: &mut Body) -> io::Result<Option<String>> {
+        let max_len = match (&*body, self.unbuffered) {
+            (Body::Static(bytes), _) => return Ok(Some(content_hash(bytes))),
+            (Body::Owned(bytes), _) => return Ok(Some(content_hash(bytes))),
+            (_, UnbufferedBodies::Skip) => return Ok(None),
+            (_, UnbufferedBodies::Hash { max_len }) => max_len,
+        };
+
+        if let Body::File(file) = body {
+            return hash_file(file, max_len);
+        }
+        match std::mem::replace(body, Body::empty()) {
+            Body::Stream(stream) => {
+                let (buffered, hash) = buffer_stream(stream, max_len)?;
+                *body = buffered;
+                Ok(hash)
+            }
+            other => {
+                *body = other;
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Middleware for AutoEtag {
+    fn after(&self, req: &mut dyn RequestExt, res: AfterResult) -> AfterResult {
+        let mut res = res?;
+        if !(*req.method() == Method::GET || *req.method() == Method::HEAD)
+            || res.status() != StatusCode::OK
+            || res.headers().contains_key(header::ETAG)
+        {
+            return Ok(res);
+        }
+
+        if let Some(hash) = self.hash(res.body_mut()).map_err(box_error)? {
+            let etag = if self.weak {
+                format!("W/\"{}\"", hash)
+            } else {
+                format!("\"{}\"", hash)
+            };
+            let etag = header::HeaderValue::from_str(&etag).map_err(box_error)?;
+            res.headers_mut().insert(header::ETAG, etag);
+        }
+        Ok(res)
+    }
+}
+
+/// Hash the rest of the file, unless it's longer than `max_len`, and seek
+/// back to where it was.
+fn hash_file(file: &mut File, max_len: u64) -> io::Result<Option<String>> {
+    let start = file.stream_position()?;
+    if file.metadata()?.len().saturating_sub(start) > max_len {
+        return Ok(None);
+    }
+    let hash = read_content_hash(file)?;
+    file.seek(SeekFrom::Start(start))?;
+    Ok(Some(hash))
+}
+
+/// Read the stream into memory to hash it, unless it's longer than
+/// `max_len`, returning the body to send in its place.
+fn buffer_stream(
+    mut stream: Box<dyn Read + Send>,
+    max_len: u64,
+) -> io::Result<(Body, Option<String>)> {
+    let mut buffered = Vec::new();
+    (&mut stream)
+        .take(max_len.saturating_add(1))
+        .read_to_end(&mut buffered)?;
+    if buffered.len() as u64 > max_len {
+        let body = Body::Stream(Box::new(Cursor::new(buffered).chain(stream)));
+        return Ok((body, None));
+    }
+    let hash = content_hash(&buffered);
+    Ok((Body::Owned(buffered), Some(hash)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{self, Write};
+
+    use conduit::{header, Body, Handler, HttpResult, Method, RequestExt, Response, StatusCode};
+    use conduit_middleware::MiddlewareBuilder;
+    use conduit_test::{MockRequest, ResponseExt};
+    use tempdir::TempDir;
+
+    use super::{AutoEtag, UnbufferedBodies};
+    use crate::ConditionalGet;
+
+    const HELLO_ETAG: &str = "\"2cf24dba5fb0a30e26e83b2ac5b9e29e\"";
+
+    fn call<H: Handler>(etag: AutoEtag, handler: H, method: Method) -> Response<Body> {
+        let mut stack = MiddlewareBuilder::new(handler);
+        stack.add(etag);
+        let mut req = MockRequest::new(method, "/");
+        stack.call(&mut req).expect("No response")
+    }
+
+    fn etag(res: &Response<Body>) -> Option<&str> {
+        res.headers()
+            .get(header::ETAG)
+            .map(|etag| etag.to_str().unwrap())
+    }
+
+    fn hello(_: &mut dyn RequestExt) -> HttpResult {
+        Response::builder().body(Body::from_static(b"hello"))
+    }
+
+    fn owned_hello(_: &mut dyn RequestExt) -> HttpResult {
+        Response::builder().body(Body::from_vec(b"hello".to_vec()))
+    }
+
+    fn stream_hello(_: &mut dyn RequestExt) -> HttpResult {
+        Response::builder().body(Body::from_reader(&b"hello"[..]))
+    }
+
+    #[test]
+    fn buffered_bodies() {
+        let res = call(AutoEtag::new(), hello, Method::GET);
+        assert_eq!(etag(&res), Some(HELLO_ETAG));
+        let res = call(AutoEtag::new(), owned_hello, Method::GET);
+        assert_eq!(etag(&res), Some(HELLO_ETAG));
+        assert_eq!(*res.into_cow(), b"hello"[..]);
+
+        let mut weak = AutoEtag::new();
+        weak.weak(true);
+        let res = call(weak, hello, Method::GET);
+        assert_eq!(etag(&res), Some(&*format!("W/{}", HELLO_ETAG)));
+
+        let res = call(AutoEtag::new(), hello, Method::HEAD);
+        assert_eq!(etag(&res), Some(HELLO_ETAG));
+    }
+
+    #[test]
+    fn skipped_responses() {
+        let res = call(AutoEtag::new(), hello, Method::POST);
+        assert_eq!(etag(&res), None);
+
+        let not_found = |_: &mut dyn RequestExt| {
+            Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from_static(b"hello"))
+        };
+        assert_eq!(etag(&call(AutoEtag::new(), not_found, Method::GET)), None);
+
+        let tagged = |_: &mut dyn RequestExt| {
+            Response::builder()
+                .header(header::ETAG, "\"v1\"")
+                .body(Body::from_static(b"hello"))
+        };
+        let res = call(AutoEtag::new(), tagged, Method::GET);
+        assert_eq!(etag(&res), Some("\"v1\""));
+    }
+
+    #[test]
+    fn streams() {
+        let res = call(AutoEtag::new(), stream_hello, Method::GET);
+        assert_eq!(etag(&res), None);
+        assert_eq!(*res.into_cow(), b"hello"[..]);
+
+        let mut hash = AutoEtag::new();
+        hash.unbuffered(UnbufferedBodies::Hash { max_len: 5 });
+        let res = call(hash.clone(), stream_hello, Method::GET);
+        assert_eq!(etag(&res), Some(HELLO_ETAG));
+        assert!(matches!(res.body(), Body::Owned(_)));
+        assert_eq!(*res.into_cow(), b"hello"[..]);
+
+        hash.unbuffered(UnbufferedBodies::Hash { max_len: 4 });
+        let res = call(hash, stream_hello, Method::GET);
+        assert_eq!(etag(&res), None);
+        assert_eq!(*res.into_cow(), b"hello"[..]);
+    }
+
+    #[test]
+    fn files() {
+        let td = TempDir::new("conduit-conditional-get").unwrap();
+        let path = td.path().join("hello.txt");
+        File::create(&path).unwrap().write_all(b"hello").unwrap();
+        let file = move |_: &mut dyn RequestExt| -> io::Result<Response<Body>> {
+            Ok(Response::new(Body::File(File::open(&path)?)))
+        };
+
+        let res = call(AutoEtag::new(), file.clone(), Method::GET);
+        assert_eq!(etag(&res), None);
+
+        let mut hash = AutoEtag::new();
+        hash.unbuffered(UnbufferedBodies::Hash { max_len: 5 });
+        let res = call(hash.clone(), file.clone(), Method::GET);
+        assert_eq!(etag(&res), Some(HELLO_ETAG));
+        assert!(matches!(res.body(), Body::File(_)));
+        assert_eq!(*res.into_cow(), b"hello"[..]);
+
+        hash.unbuffered(UnbufferedBodies::Hash { max_len: 4 });
+        let res = call(hash, file, Method::GET);
+        assert_eq!(etag(&res), None);
+    }
+
+    #[test]
+    fn with_conditional_get() {
+        let mut stack = MiddlewareBuilder::new(owned_hello);
+        stack.add(ConditionalGet);
+        stack.add(AutoEtag::new());
+
+        let mut req = MockRequest::new(Method::GET, "/");
+        req.header(header::IF_NONE_MATCH, HELLO_ETAG);
+        let res = stack.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(etag(&res), Some(HELLO_ETAG));
+
+        let mut req = MockRequest::new(Method::GET, "/");
+        req.header(header::IF_NONE_MATCH, "\"stale\"");
+        let res = stack.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(*res.into_cow(), b"hello"[..]);
+    }
+}
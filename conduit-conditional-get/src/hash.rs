@@ -0,0 +1,40 @@
+use std::io::{self, Read};
+
+use sha2::{Digest, Sha256};
+
+/// An opaque entity tag for a representation with the contents `bytes`, the
+/// first 128 bits of its SHA-256 hash in hexadecimal.
+///
+/// ```
+/// use conduit_conditional_get::content_hash;
+///
+/// assert_eq!(content_hash(b"hello"), "2cf24dba5fb0a30e26e83b2ac5b9e29e");
+/// ```
+pub fn content_hash(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes)[..16])
+}
+
+/// The `content_hash` of the contents read from `reader` until its end.
+pub fn read_content_hash<R: Read + ?Sized>(reader: &mut R) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(hex(&hasher.finalize()[..16]))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{content_hash, read_content_hash};
+
+    #[test]
+    fn reader_hash_matches() {
+        let mut reader = &b"hello"[..];
+        assert_eq!(
+            read_content_hash(&mut reader).unwrap(),
+            content_hash(b"hello")
+        );
+    }
+}
@@ -1,29 +1,51 @@
-use conduit::{header, Body, HeaderMap, Method, RequestExt, Response, StatusCode};
+use std::time::SystemTime;
+
+use conduit::header::{self, HeaderMap, HeaderName, HeaderValue};
+use conduit::{Body, Method, RequestExt, Response, StatusCode};
+use conduit_httpdate::fmt_http_date;
 use conduit_middleware::{AfterResult, Middleware};
-use std::borrow::Cow;
-use time::{OffsetDateTime, ParseError, PrimitiveDateTime};
 
+pub use crate::auto_etag::{AutoEtag, UnbufferedBodies};
+pub use crate::hash::{content_hash, read_content_hash};
+pub use crate::precondition::{EntityTag, EntityTagList, Precondition, Validators};
+pub use crate::write::{ConditionalWrite, Resource};
+
+mod auto_etag;
+mod hash;
+mod precondition;
+mod write;
+
+/// Evaluates the preconditions of `GET` and `HEAD` requests against the
+/// `ETag` and `Last-Modified` headers of `200 OK` responses, see
+/// `Validators::evaluate`. Use `ConditionalWrite` for other methods, whose
+/// preconditions must be evaluated before the handler runs.
+///
+/// Responses are replaced by `304 Not Modified` or `412 Precondition
+/// Failed` responses accordingly. A `304` only keeps the headers listed by
+/// RFC 7232 §4.1, and `Last-Modified` if there is no `ETag`, and gets a
+/// `Date` if it has none. Responses with `Vary: *` depend on more
+/// than the request, so they are never replaced by a `304`.
+///
+/// Other `Vary` fields need no special handling: the validators compared
+/// are those of the representation the handler selected for this request,
+/// and the `304` keeps the `Vary` header for caches to key on.
 #[allow(missing_copy_implementations)]
 pub struct ConditionalGet;
 
 impl Middleware for ConditionalGet {
     fn after(&self, req: &mut dyn RequestExt, res: AfterResult) -> AfterResult {
         let res = res?;
-
-        match *req.method() {
-            Method::GET | Method::HEAD => {
-                if is_ok(&res) && is_fresh(req, &res) {
-                    let (mut parts, _) = res.into_parts();
-                    parts.status = StatusCode::NOT_MODIFIED;
-                    parts.headers.remove(header::CONTENT_TYPE);
-                    parts.headers.remove(header::CONTENT_LENGTH);
-                    return Ok(Response::from_parts(parts, Body::empty()));
-                }
-            }
-            _ => (),
+        let method = req.method();
+        if !(*method == Method::GET || *method == Method::HEAD) || !is_ok(&res) {
+            return Ok(res);
         }
 
-        Ok(res)
+        match Validators::from_headers(res.headers()).evaluate(method, req.headers()) {
+            Precondition::Passed => Ok(res),
+            Precondition::NotModified if varies_on_everything(res.headers()) => Ok(res),
+            Precondition::NotModified => Ok(not_modified(res)),
+            Precondition::Failed => Ok(precondition_failed()),
+        }
     }
 }
 
@@ -31,87 +53,68 @@ fn is_ok(response: &Response<Body>) -> bool {
     response.status() == 200
 }
 
-fn is_fresh(req: &dyn RequestExt, res: &Response<Body>) -> bool {
-    let modified_since = get_and_concat_header(req.headers(), header::IF_MODIFIED_SINCE);
-    let none_match = get_and_concat_header(req.headers(), header::IF_NONE_MATCH);
-
-    if modified_since.is_empty() && none_match.is_empty() {
-        return false;
-    }
-
-    let is_modified_since = match std::str::from_utf8(&modified_since) {
-        Err(_) => true,
-        Ok(string) if string.is_empty() => true,
-        Ok(modified_since) => {
-            let modified_since = parse_http_date(modified_since);
-            match modified_since {
-                Err(_) => return false, // Preserve existing behavior
-                Ok(parsed) => is_modified_since(parsed, res),
-            }
+/// The headers a `304 Not Modified` response keeps from the `200 OK`
+/// response it replaces.
+const NOT_MODIFIED_HEADERS: [HeaderName; 6] = [
+    header::CACHE_CONTROL,
+    header::CONTENT_LOCATION,
+    header::DATE,
+    header::ETAG,
+    header::EXPIRES,
+    header::VARY,
+];
+
+fn not_modified(res: Response<Body>) -> Response<Body> {
+    // The original body is dropped without being read, which also avoids
+    // driving a `Body::Stream` for a 304.
+    let (mut parts, _) = res.into_parts();
+    let mut headers = HeaderMap::new();
+    for (name, value) in &parts.headers {
+        // `Last-Modified` is only useful to caches without an `ETag`
+        let last_modified =
+            name == header::LAST_MODIFIED && !parts.headers.contains_key(header::ETAG);
+        if NOT_MODIFIED_HEADERS.contains(name) || last_modified {
+            headers.append(name.clone(), value.clone());
         }
-    };
-
-    is_modified_since && etag_matches(&none_match, res)
-}
-
-fn etag_matches(none_match: &[u8], res: &Response<Body>) -> bool {
-    let value = get_and_concat_header(res.headers(), header::ETAG);
-    value == none_match
-}
-
-fn is_modified_since(modified_since: OffsetDateTime, res: &Response<Body>) -> bool {
-    let last_modified = get_and_concat_header(res.headers(), header::LAST_MODIFIED);
-
-    match std::str::from_utf8(&last_modified) {
-        Err(_) => false,
-        Ok(last_modified) => match parse_http_date(last_modified) {
-            Err(_) => false,
-            Ok(last_modified) => modified_since.unix_timestamp() >= last_modified.unix_timestamp(),
-        },
     }
-}
-
-fn get_and_concat_header(headers: &HeaderMap, name: header::HeaderName) -> Cow<'_, [u8]> {
-    let mut values = headers.get_all(name).iter();
-    if values.size_hint() == (1, Some(1)) {
-        // Exactly 1 value, allocation is unnecessary
-        // Unwrap will not panic, because there is a value
-        Cow::Borrowed(values.next().unwrap().as_bytes())
-    } else {
-        let values: Vec<_> = values.map(|val| val.as_bytes()).collect();
-        Cow::Owned(values.concat())
+    if !headers.contains_key(header::DATE) {
+        let date = fmt_http_date(SystemTime::now());
+        headers.insert(header::DATE, HeaderValue::from_str(&date).unwrap());
     }
-}
 
-fn parse_http_date(string: &str) -> Result<OffsetDateTime, ()> {
-    parse_rfc1123(string)
-        .or_else(|_| parse_rfc850(string))
-        .or_else(|_| parse_asctime(string))
-        .map_err(|_| ())
+    parts.status = StatusCode::NOT_MODIFIED;
+    parts.headers = headers;
+    Response::from_parts(parts, Body::empty())
 }
 
-fn parse_rfc1123(string: &str) -> Result<OffsetDateTime, ParseError> {
-    Ok(PrimitiveDateTime::parse(string, "%a, %d %b %Y %T GMT")?.assume_utc())
+fn varies_on_everything(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|field| field.trim() == "*")
 }
 
-fn parse_rfc850(string: &str) -> Result<OffsetDateTime, ParseError> {
-    Ok(PrimitiveDateTime::parse(string, "%a, %d-%m-%y %T GMT")?.assume_utc())
-}
-
-fn parse_asctime(string: &str) -> Result<OffsetDateTime, ParseError> {
-    // TODO: should this be "%a %b %d %T %Y"?
-    Ok(PrimitiveDateTime::parse(string, "%a %m\t%d %T %Y")?.assume_utc())
+fn precondition_failed() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::PRECONDITION_FAILED)
+        .header(header::CONTENT_LENGTH, 0)
+        .body(Body::empty())
+        .unwrap()
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::{Duration, SystemTime};
+
     use conduit::{
         box_error, header, Body, Handler, HandlerResult, HeaderMap, Method, RequestExt, Response,
         StatusCode,
     };
+    use conduit_httpdate::fmt_http_date;
     use conduit_middleware::MiddlewareBuilder;
     use conduit_test::{MockRequest, ResponseExt};
-    use time::{Duration, OffsetDateTime};
 
     use super::ConditionalGet;
 
@@ -120,7 +123,7 @@ mod tests {
             use std::convert::TryInto;
             let mut headers = HeaderMap::new();
             $(headers.append($header, $value.try_into().unwrap());)+
-            let handler = SimpleHandler::new(headers, $status, "hello");
+            let handler = SimpleHandler::new(headers, $status, "hello", false);
             let mut stack = MiddlewareBuilder::new(handler);
             stack.add(ConditionalGet);
             stack
@@ -130,6 +133,18 @@ mod tests {
         })
     }
 
+    macro_rules! streaming {
+        ($($header:expr => $value:expr),+) => ({
+            use std::convert::TryInto;
+            let mut headers = HeaderMap::new();
+            $(headers.append($header, $value.try_into().unwrap());)+
+            let handler = SimpleHandler::new(headers, StatusCode::OK, "hello", true);
+            let mut stack = MiddlewareBuilder::new(handler);
+            stack.add(ConditionalGet);
+            stack
+        })
+    }
+
     macro_rules! request {
         ($($header:expr => $value:expr),+) => ({
             let mut req = MockRequest::new(Method::GET, "/");
@@ -140,9 +155,9 @@ mod tests {
 
     #[test]
     fn test_sends_304() {
-        let handler = returning!(header::LAST_MODIFIED => httpdate(OffsetDateTime::now_utc()));
+        let handler = returning!(header::LAST_MODIFIED => now());
         expect_304(handler.call(&mut request!(
-            header::IF_MODIFIED_SINCE => httpdate(OffsetDateTime::now_utc())
+            header::IF_MODIFIED_SINCE => now()
         )));
     }
 
@@ -150,7 +165,7 @@ mod tests {
     fn test_sends_304_if_older_than_now() {
         let handler = returning!(header::LAST_MODIFIED => before_now());
         expect_304(handler.call(&mut request!(
-            header::IF_MODIFIED_SINCE => httpdate(OffsetDateTime::now_utc())
+            header::IF_MODIFIED_SINCE => now()
         )));
     }
 
@@ -172,9 +187,10 @@ mod tests {
     }
 
     #[test]
-    fn test_sends_200_with_fresh_etag_but_not_time() {
+    fn test_sends_304_with_fresh_etag_but_not_time() {
+        // If-None-Match takes precedence over If-Modified-Since
         let handler = returning!(header::LAST_MODIFIED => now(), header::ETAG => "1234");
-        expect_200(handler.call(&mut request!(
+        expect_304(handler.call(&mut request!(
             header::IF_MODIFIED_SINCE => before_now(),
             header::IF_NONE_MATCH => "1234"
         )));
@@ -205,6 +221,117 @@ mod tests {
         )));
     }
 
+    #[test]
+    fn test_sends_304_with_etag_list() {
+        let handler = returning!(header::ETAG => "\"b\"");
+        expect_304(handler.call(&mut request!(
+            header::IF_NONE_MATCH => "\"a\", \"b\""
+        )));
+    }
+
+    #[test]
+    fn test_sends_304_with_weak_etag() {
+        let handler = returning!(header::ETAG => "W/\"1234\"");
+        expect_304(handler.call(&mut request!(
+            header::IF_NONE_MATCH => "\"1234\""
+        )));
+    }
+
+    #[test]
+    fn test_sends_304_with_any_etag() {
+        let handler = returning!(header::LAST_MODIFIED => now());
+        expect_304(handler.call(&mut request!(
+            header::IF_NONE_MATCH => "*"
+        )));
+    }
+
+    #[test]
+    fn test_304_keeps_cache_headers() {
+        let handler = returning!(
+            header::ETAG => "\"1234\"",
+            header::LAST_MODIFIED => before_now(),
+            header::CACHE_CONTROL => "max-age=60",
+            header::EXPIRES => now(),
+            header::VARY => "Accept-Encoding",
+            header::CONTENT_LOCATION => "/hello.txt",
+            header::SET_COOKIE => "session=1",
+            header::CONTENT_TYPE => "text/plain",
+            header::CONTENT_ENCODING => "gzip",
+            header::CONTENT_LANGUAGE => "en",
+            header::CONTENT_LENGTH => "5",
+            header::ACCEPT_RANGES => "bytes",
+            "x-custom" => "1"
+        );
+        let response = handler
+            .call(&mut request!(header::IF_NONE_MATCH => "\"1234\""))
+            .expect("No response");
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let mut names = response
+            .headers()
+            .keys()
+            .map(|name| name.as_str())
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                "cache-control",
+                "content-location",
+                "date",
+                "etag",
+                "expires",
+                "vary"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_304_drops_other_headers() {
+        let handler = returning!(header::ETAG => "\"1234\"", "x-custom" => "1");
+        let response = handler
+            .call(&mut request!(header::IF_NONE_MATCH => "\"1234\""))
+            .expect("No response");
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(!response.headers().contains_key("x-custom"));
+        assert_eq!(response.headers()[header::ETAG], "\"1234\"");
+    }
+
+    #[test]
+    fn test_304_keeps_last_modified_without_etag() {
+        let date = "Sun, 06 Nov 1994 08:49:37 GMT";
+        let modified = before_now();
+        let handler = returning!(header::LAST_MODIFIED => &*modified, header::DATE => date);
+        let response = handler
+            .call(&mut request!(header::IF_MODIFIED_SINCE => now()))
+            .expect("No response");
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::LAST_MODIFIED], *modified);
+        assert_eq!(response.headers()[header::DATE], date);
+    }
+
+    #[test]
+    fn test_sends_200_when_varying_on_everything() {
+        let handler = returning!(header::ETAG => "\"1234\"", header::VARY => "Cookie, *");
+        expect_200(handler.call(&mut request!(
+            header::IF_NONE_MATCH => "\"1234\""
+        )));
+    }
+
+    #[test]
+    fn test_sends_412_with_failed_if_match() {
+        let handler = returning!(header::ETAG => "\"1234\"");
+        let response = handler
+            .call(&mut request!(header::IF_MATCH => "W/\"1234\""))
+            .expect("No response");
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        assert_eq!(*response.into_cow(), b""[..]);
+
+        expect_200(handler.call(&mut request!(
+            header::IF_MATCH => "\"4321\", \"1234\""
+        )));
+    }
+
     #[test]
     fn test_does_not_affect_non_200() {
         let handler = returning!(StatusCode::FOUND, header::LAST_MODIFIED => before_now(), header::ETAG => "1234");
@@ -217,15 +344,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_does_not_affect_other_methods() {
+        let handler = returning!(header::ETAG => "\"1234\"");
+        let mut req = MockRequest::new(Method::PUT, "/");
+        req.header(header::IF_NONE_MATCH, "*");
+        let response = handler.call(&mut req).expect("No response");
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::ETAG], "\"1234\"");
+        assert_eq!(*response.into_cow(), b"hello"[..]);
+    }
+
     #[test]
     fn test_does_not_affect_malformed_timestamp() {
-        let bad_stamp = OffsetDateTime::now_utc().format("%Y-%m-%d %H:%M:%S %z");
+        let bad_stamp = "2024-01-01 00:00:00 +0000";
         let handler = returning!(header::LAST_MODIFIED => before_now());
         expect_200(handler.call(&mut request!(
             header::IF_MODIFIED_SINCE => bad_stamp
         )));
     }
 
+    #[test]
+    fn test_sends_304_for_streaming_body() {
+        let handler = streaming!(header::ETAG => "1234");
+        expect_304(handler.call(&mut request!(
+            header::IF_NONE_MATCH => "1234"
+        )));
+    }
+
+    #[test]
+    fn test_sends_200_for_streaming_body() {
+        let handler = streaming!(header::ETAG => "1234");
+        expect_200(handler.call(&mut request!(
+            header::IF_NONE_MATCH => "4321"
+        )));
+    }
+
     fn expect_304(response: HandlerResult) {
         let response = response.expect("No response");
         assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
@@ -246,14 +400,21 @@ mod tests {
         headers: HeaderMap,
         status: StatusCode,
         body: &'static str,
+        stream: bool,
     }
 
     impl SimpleHandler {
-        fn new(headers: HeaderMap, status: StatusCode, body: &'static str) -> SimpleHandler {
+        fn new(
+            headers: HeaderMap,
+            status: StatusCode,
+            body: &'static str,
+            stream: bool,
+        ) -> SimpleHandler {
             SimpleHandler {
                 headers,
                 status,
                 body,
+                stream,
             }
         }
     }
@@ -262,22 +423,20 @@ mod tests {
         fn call(&self, _: &mut dyn RequestExt) -> HandlerResult {
             let mut builder = Response::builder().status(self.status);
             builder.headers_mut().unwrap().extend(self.headers.clone());
-            builder
-                .body(Body::from_static(self.body.as_bytes()))
-                .map_err(box_error)
+            let body = if self.stream {
+                Body::from_reader(self.body.as_bytes())
+            } else {
+                Body::from_static(self.body.as_bytes())
+            };
+            builder.body(body).map_err(box_error)
         }
     }
 
     fn before_now() -> String {
-        let now = OffsetDateTime::now_utc();
-        httpdate(now - Duration::weeks(52))
+        fmt_http_date(SystemTime::now() - Duration::from_secs(52 * 7 * 86_400))
     }
 
     fn now() -> String {
-        httpdate(OffsetDateTime::now_utc())
-    }
-
-    fn httpdate(time: OffsetDateTime) -> String {
-        time.format("%a, %d-%m-%y %T GMT")
+        fmt_http_date(SystemTime::now())
     }
 }
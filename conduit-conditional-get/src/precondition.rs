@@ -0,0 +1,460 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use conduit::{header, HeaderMap, Method};
+use conduit_httpdate::parse_http_date;
+
+/// An entity tag, such as `"xyzzy"` or `W/"xyzzy"`.
+///
+/// Tags without quotes are accepted too, and only equal to tags without
+/// quotes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntityTag<'a> {
+    weak: bool,
+    opaque: &'a str,
+}
+
+impl<'a> EntityTag<'a> {
+    /// Parse the value of an `ETag` header.
+    pub fn parse(value: &'a str) -> Option<EntityTag<'a>> {
+        match parse_tag(value.trim()) {
+            Some((tag, "")) => Some(tag),
+            _ => None,
+        }
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// The opaque tag, including its quotes.
+    pub fn opaque(&self) -> &'a str {
+        self.opaque
+    }
+
+    /// Whether both tags are strong and equal, as used by `If-Match`.
+    pub fn strong_eq(&self, other: &EntityTag<'_>) -> bool {
+        !self.weak && !other.weak && self.opaque == other.opaque
+    }
+
+    /// Whether both tags are equal, strong or not, as used by
+    /// `If-None-Match`.
+    pub fn weak_eq(&self, other: &EntityTag<'_>) -> bool {
+        self.opaque == other.opaque
+    }
+}
+
+/// Parse an entity tag at the start of `input`, returning it and the rest
+/// of the input.
+fn parse_tag(input: &str) -> Option<(EntityTag<'_>, &str)> {
+    let (weak, input) = match input.strip_prefix("W/") {
+        Some(input) => (true, input),
+        None => (false, input),
+    };
+    let end = match input.strip_prefix('"') {
+        Some(rest) => rest.find('"')? + 2,
+        None => input.find(',').unwrap_or(input.len()),
+    };
+    let opaque = input[..end].trim_end();
+    if opaque.is_empty() {
+        return None;
+    }
+    Some((EntityTag { weak, opaque }, &input[end..]))
+}
+
+/// The value of an `If-Match` or `If-None-Match` header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntityTagList<'a> {
+    /// `*`, matching any current representation.
+    Any,
+    Tags(Vec<EntityTag<'a>>),
+}
+
+impl<'a> EntityTagList<'a> {
+    /// Parse all the values of the header `name`, or `None` if the request
+    /// has none.
+    ///
+    /// Invalid elements of the list are ignored.
+    pub fn from_headers(headers: &'a HeaderMap, name: header::HeaderName) -> Option<Self> {
+        let mut values = headers.get_all(name).iter().peekable();
+        values.peek()?;
+
+        let mut tags = Vec::new();
+        for value in values {
+            let mut input = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            loop {
+                input = input.trim_start_matches(|c: char| c == ',' || c.is_ascii_whitespace());
+                if input.is_empty() {
+                    break;
+                }
+                if let Some(rest) = input.strip_prefix('*') {
+                    if rest.trim_start().is_empty() || rest.trim_start().starts_with(',') {
+                        return Some(EntityTagList::Any);
+                    }
+                }
+                match parse_tag(input) {
+                    Some((tag, rest)) => {
+                        tags.push(tag);
+                        input = rest;
+                    }
+                    // Skip to the next element
+                    None => input = input.find(',').map_or("", |i| &input[i..]),
+                }
+            }
+        }
+        Some(EntityTagList::Tags(tags))
+    }
+
+    /// Whether the list matches the entity tag of the current
+    /// representation, if there is one.
+    fn matches(&self, current: Option<&EntityTag<'_>>, strong: bool) -> bool {
+        match (self, current) {
+            (EntityTagList::Any, _) => true,
+            (EntityTagList::Tags(_), None) => false,
+            (EntityTagList::Tags(tags), Some(current)) => tags.iter().any(|tag| {
+                if strong {
+                    tag.strong_eq(current)
+                } else {
+                    tag.weak_eq(current)
+                }
+            }),
+        }
+    }
+}
+
+/// The result of evaluating the preconditions of a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Precondition {
+    /// No precondition failed, the request should be handled normally.
+    Passed,
+    /// The representation wasn't modified, answer with `304 Not Modified`.
+    NotModified,
+    /// A precondition failed, answer with `412 Precondition Failed`.
+    Failed,
+}
+
+/// The validators of the current representation of a resource, to evaluate
+/// the preconditions of a request against.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Validators<'a> {
+    pub etag: Option<EntityTag<'a>>,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl<'a> Validators<'a> {
+    /// The validators in the `ETag` and `Last-Modified` headers of a
+    /// response.
+    pub fn from_headers(headers: &'a HeaderMap) -> Self {
+        Validators {
+            etag: headers
+                .get(header::ETAG)
+                .and_then(|value| value.to_str().ok())
+                .and_then(EntityTag::parse),
+            last_modified: header_date(headers, header::LAST_MODIFIED),
+        }
+    }
+
+    /// Evaluate the preconditions of a request for the representation, in
+    /// the order of RFC 7232, section 6:
+    ///
+    /// 1. `If-Match`, or `If-Unmodified-Since` without it, fail the request
+    ///    if they don't match.
+    /// 2. `If-None-Match`, or `If-Modified-Since` without it for `GET` and
+    ///    `HEAD` requests, answer with `304 Not Modified` for `GET` and
+    ///    `HEAD` requests, and fail other requests if they match.
+    ///
+    /// Invalid dates are ignored, as are dates when the representation has
+    /// no modification date.
+    pub fn evaluate(&self, method: &Method, headers: &HeaderMap) -> Precondition {
+        let etag = self.etag.as_ref();
+        if let Some(if_match) = EntityTagList::from_headers(headers, header::IF_MATCH) {
+            if !if_match.matches(etag, true) {
+                return Precondition::Failed;
+            }
+        } else if let (Some(since), Some(modified)) = (
+            header_date(headers, header::IF_UNMODIFIED_SINCE),
+            self.last_modified,
+        ) {
+            if secs(modified) > secs(since) {
+                return Precondition::Failed;
+            }
+        }
+
+        let safe = matches!(*method, Method::GET | Method::HEAD);
+        if let Some(if_none_match) = EntityTagList::from_headers(headers, header::IF_NONE_MATCH) {
+            if if_none_match.matches(etag, false) {
+                return if safe {
+                    Precondition::NotModified
+                } else {
+                    Precondition::Failed
+                };
+            }
+        } else if let (true, Some(since), Some(modified)) = (
+            safe,
+            header_date(headers, header::IF_MODIFIED_SINCE),
+            self.last_modified,
+        ) {
+            if secs(modified) <= secs(since) {
+                return Precondition::NotModified;
+            }
+        }
+
+        Precondition::Passed
+    }
+}
+
+fn header_date(headers: &HeaderMap, name: header::HeaderName) -> Option<SystemTime> {
+    let value = headers.get(name)?.to_str().ok()?;
+    parse_http_date(value).ok()
+}
+
+/// The seconds since the Unix epoch, as HTTP dates have a resolution of a
+/// second.
+fn secs(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use conduit::{header, HeaderMap, Method};
+
+    use super::{EntityTag, EntityTagList, Precondition, Validators};
+
+    fn tag(weak: bool, opaque: &str) -> EntityTag<'_> {
+        EntityTag { weak, opaque }
+    }
+
+    fn header_map(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn parse_entity_tag() {
+        assert_eq!(EntityTag::parse("\"xyzzy\""), Some(tag(false, "\"xyzzy\"")));
+        assert_eq!(
+            EntityTag::parse(" W/\"xyzzy\" "),
+            Some(tag(true, "\"xyzzy\""))
+        );
+        assert_eq!(EntityTag::parse("\"\""), Some(tag(false, "\"\"")));
+        assert_eq!(EntityTag::parse("1234"), Some(tag(false, "1234")));
+        assert_eq!(EntityTag::parse("\"xyzzy"), None);
+        assert_eq!(EntityTag::parse("\"a\" \"b\""), None);
+        assert_eq!(EntityTag::parse(""), None);
+    }
+
+    #[test]
+    fn parse_list() {
+        let list = |value: &str| {
+            let headers = header_map(&[(header::IF_NONE_MATCH, value)]);
+            let list = EntityTagList::from_headers(&headers, header::IF_NONE_MATCH);
+            format!("{:?}", list.unwrap())
+        };
+
+        assert_eq!(list("*"), "Any");
+        assert_eq!(
+            list("\"a\", W/\"b\",\"c,d\" ,"),
+            format!(
+                "{:?}",
+                EntityTagList::Tags(vec![
+                    tag(false, "\"a\""),
+                    tag(true, "\"b\""),
+                    tag(false, "\"c,d\""),
+                ])
+            )
+        );
+        assert_eq!(list(""), "Tags([])");
+
+        let headers = header_map(&[
+            (header::IF_NONE_MATCH, "\"a\""),
+            (header::IF_NONE_MATCH, "\"b\""),
+        ]);
+        assert_eq!(
+            EntityTagList::from_headers(&headers, header::IF_NONE_MATCH),
+            Some(EntityTagList::Tags(vec![
+                tag(false, "\"a\""),
+                tag(false, "\"b\"")
+            ]))
+        );
+        assert_eq!(
+            EntityTagList::from_headers(&headers, header::IF_MATCH),
+            None
+        );
+    }
+
+    #[test]
+    fn comparison() {
+        // The examples of RFC 7232, section 2.3.2
+        let cases = [
+            (tag(true, "\"1\""), tag(true, "\"1\""), false, true),
+            (tag(true, "\"1\""), tag(true, "\"2\""), false, false),
+            (tag(true, "\"1\""), tag(false, "\"1\""), false, true),
+            (tag(false, "\"1\""), tag(false, "\"1\""), true, true),
+        ];
+        for (a, b, strong, weak) in cases {
+            assert_eq!(a.strong_eq(&b), strong, "{:?} {:?}", a, b);
+            assert_eq!(a.weak_eq(&b), weak, "{:?} {:?}", a, b);
+        }
+    }
+
+    const NOW: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+    const BEFORE: &str = "Sat, 05 Nov 1994 08:49:37 GMT";
+    const AFTER: &str = "Mon, 07 Nov 1994 08:49:37 GMT";
+
+    fn evaluate(
+        method: Method,
+        etag: Option<&str>,
+        pairs: &[(header::HeaderName, &str)],
+    ) -> Precondition {
+        let validators = Validators {
+            etag: etag.and_then(EntityTag::parse),
+            last_modified: Some(UNIX_EPOCH + Duration::from_secs(784_111_777)),
+        };
+        validators.evaluate(&method, &header_map(pairs))
+    }
+
+    #[test]
+    fn if_match() {
+        use Precondition::*;
+
+        let cases = [
+            (Some("\"a\""), "\"a\"", Passed),
+            (Some("\"a\""), "\"b\", \"a\"", Passed),
+            (Some("\"a\""), "*", Passed),
+            (None, "*", Passed),
+            (Some("\"a\""), "\"b\"", Failed),
+            (Some("W/\"a\""), "W/\"a\"", Failed),
+            (Some("\"a\""), "W/\"a\"", Failed),
+            (None, "\"a\"", Failed),
+        ];
+        for (etag, if_match, expected) in cases {
+            for method in [Method::GET, Method::PUT] {
+                let actual = evaluate(method, etag, &[(header::IF_MATCH, if_match)]);
+                assert_eq!(actual, expected, "{:?} {:?}", etag, if_match);
+            }
+        }
+    }
+
+    #[test]
+    fn if_unmodified_since() {
+        use Precondition::*;
+
+        let cases = [
+            (NOW, Passed),
+            (AFTER, Passed),
+            (BEFORE, Failed),
+            ("invalid", Passed),
+        ];
+        for (since, expected) in cases {
+            let actual = evaluate(Method::PUT, None, &[(header::IF_UNMODIFIED_SINCE, since)]);
+            assert_eq!(actual, expected, "{:?}", since);
+        }
+
+        // If-Match takes precedence
+        let actual = evaluate(
+            Method::PUT,
+            Some("\"a\""),
+            &[
+                (header::IF_MATCH, "\"a\""),
+                (header::IF_UNMODIFIED_SINCE, BEFORE),
+            ],
+        );
+        assert_eq!(actual, Passed);
+    }
+
+    #[test]
+    fn if_none_match() {
+        use Precondition::*;
+
+        let cases = [
+            (Some("\"a\""), "\"a\"", NotModified, Failed),
+            (Some("\"a\""), "\"b\", \"a\"", NotModified, Failed),
+            (Some("W/\"a\""), "\"a\"", NotModified, Failed),
+            (Some("\"a\""), "W/\"a\"", NotModified, Failed),
+            (Some("\"a\""), "*", NotModified, Failed),
+            (None, "*", NotModified, Failed),
+            (Some("\"a\""), "\"b\"", Passed, Passed),
+            (None, "\"a\"", Passed, Passed),
+        ];
+        for (etag, if_none_match, safe, unsafe_) in cases {
+            let headers = [(header::IF_NONE_MATCH, if_none_match)];
+            assert_eq!(evaluate(Method::GET, etag, &headers), safe);
+            assert_eq!(evaluate(Method::HEAD, etag, &headers), safe);
+            assert_eq!(evaluate(Method::POST, etag, &headers), unsafe_);
+        }
+    }
+
+    #[test]
+    fn if_modified_since() {
+        use Precondition::*;
+
+        let cases = [
+            (NOW, NotModified),
+            (AFTER, NotModified),
+            (BEFORE, Passed),
+            ("Sunday, 06-Nov-94 08:49:37 GMT", NotModified),
+            ("Sun Nov  6 08:49:37 1994", NotModified),
+            ("Sat Nov  5 08:49:37 1994", Passed),
+            ("invalid", Passed),
+        ];
+        for (since, expected) in cases {
+            let headers = [(header::IF_MODIFIED_SINCE, since)];
+            assert_eq!(
+                evaluate(Method::GET, None, &headers),
+                expected,
+                "{:?}",
+                since
+            );
+            assert_eq!(evaluate(Method::POST, None, &headers), Passed);
+        }
+
+        // If-None-Match takes precedence, whether it matches or not
+        let headers = [
+            (header::IF_NONE_MATCH, "\"b\""),
+            (header::IF_MODIFIED_SINCE, NOW),
+        ];
+        assert_eq!(evaluate(Method::GET, Some("\"a\""), &headers), Passed);
+        let headers = [
+            (header::IF_NONE_MATCH, "\"a\""),
+            (header::IF_MODIFIED_SINCE, BEFORE),
+        ];
+        assert_eq!(evaluate(Method::GET, Some("\"a\""), &headers), NotModified);
+    }
+
+    #[test]
+    fn evaluation_order() {
+        use Precondition::*;
+
+        // A failed If-Match wins over a matching If-None-Match
+        let headers = [
+            (header::IF_MATCH, "\"b\""),
+            (header::IF_NONE_MATCH, "\"a\""),
+        ];
+        assert_eq!(evaluate(Method::GET, Some("\"a\""), &headers), Failed);
+
+        let headers = [
+            (header::IF_UNMODIFIED_SINCE, BEFORE),
+            (header::IF_MODIFIED_SINCE, NOW),
+        ];
+        assert_eq!(evaluate(Method::GET, None, &headers), Failed);
+
+        // Without a modification date, dates are ignored
+        let validators = Validators::default();
+        let headers = header_map(&[
+            (header::IF_UNMODIFIED_SINCE, BEFORE),
+            (header::IF_MODIFIED_SINCE, NOW),
+        ]);
+        assert_eq!(validators.evaluate(&Method::GET, &headers), Passed);
+        assert_eq!(validators.evaluate(&Method::GET, &HeaderMap::new()), Passed);
+    }
+}
@@ -0,0 +1,276 @@
+use std::time::SystemTime;
+
+use conduit::{header, BoxError, Handler, HandlerResult, Method, RequestExt};
+use conduit_middleware::AroundMiddleware;
+
+use crate::precondition::{EntityTag, Precondition, Validators};
+
+/// The validators of the current state of a resource, as loaded by the
+/// function of a `ConditionalWrite`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Resource {
+    /// The entity tag of the current representation, such as `"v2"`.
+    pub etag: Option<String>,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Resource {
+    fn validators(&self) -> Validators<'_> {
+        Validators {
+            etag: self.etag.as_deref().and_then(EntityTag::parse),
+            last_modified: self.last_modified,
+        }
+    }
+}
+
+/// Evaluates the preconditions of requests with unsafe methods, such as
+/// `PUT` or `DELETE`, before calling the handler.
+///
+/// The current state of the target resource is loaded by a function, which
+/// returns `None` if the resource doesn't exist. It's only called for
+/// requests with one of these headers. The handler isn't called
+/// and `412 Precondition Failed` is returned if `If-Match`,
+/// `If-Unmodified-Since` or `If-None-Match` don't match the resource, so
+/// that clients can't overwrite changes they haven't seen.
+///
+/// ```
+/// # use conduit::{Body, RequestExt, Response};
+/// # use conduit_conditional_get::{ConditionalWrite, Resource};
+/// # use conduit_middleware::MiddlewareBuilder;
+/// # fn update(_: &mut dyn RequestExt) -> conduit::HttpResult {
+/// #     Response::builder().body(Body::empty())
+/// # }
+/// let mut stack = MiddlewareBuilder::new(update);
+/// stack.around(ConditionalWrite::new(|_: &dyn RequestExt| {
+///     Ok(Some(Resource {
+///         etag: Some("\"v2\"".into()),
+///         last_modified: None,
+///     }))
+/// }));
+/// ```
+pub struct ConditionalWrite<F> {
+    load: F,
+    handler: Option<Box<dyn Handler>>,
+}
+
+impl<F> ConditionalWrite<F>
+where
+    F: Fn(&dyn RequestExt) -> Result<Option<Resource>, BoxError> + Send + Sync + 'static,
+{
+    pub fn new(load: F) -> Self {
+        ConditionalWrite {
+            load,
+            handler: None,
+        }
+    }
+
+    fn evaluate(&self, req: &dyn RequestExt) -> Result<Precondition, BoxError> {
+        // Most requests have no preconditions, so don't load the resource
+        let headers = req.headers();
+        let conditional = [
+            header::IF_MATCH,
+            header::IF_UNMODIFIED_SINCE,
+            header::IF_NONE_MATCH,
+        ];
+        if !conditional.iter().any(|name| headers.contains_key(name)) {
+            return Ok(Precondition::Passed);
+        }
+
+        let precondition = match (self.load)(req)? {
+            Some(resource) => resource.validators().evaluate(req.method(), headers),
+            // `If-Match` requires a current representation, while
+            // `If-None-Match` can't match a missing one.
+            None if headers.contains_key(header::IF_MATCH) => Precondition::Failed,
+            None => Precondition::Passed,
+        };
+        Ok(precondition)
+    }
+}
+
+impl<F> AroundMiddleware for ConditionalWrite<F>
+where
+    F: Fn(&dyn RequestExt) -> Result<Option<Resource>, BoxError> + Send + Sync + 'static,
+{
+    fn with_handler(&mut self, handler: Box<dyn Handler>) {
+        self.handler = Some(handler);
+    }
+}
+
+impl<F> Handler for ConditionalWrite<F>
+where
+    F: Fn(&dyn RequestExt) -> Result<Option<Resource>, BoxError> + Send + Sync + 'static,
+{
+    fn call(&self, req: &mut dyn RequestExt) -> HandlerResult {
+        let safe = matches!(
+            *req.method(),
+            Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+        );
+        if !safe && self.evaluate(req)? == Precondition::Failed {
+            return Ok(crate::precondition_failed());
+        }
+
+        let handler = self
+            .handler
+            .as_ref()
+            .expect("ConditionalWrite must wrap a handler");
+        handler.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use conduit::{box_error, header, Body, Handler, Method, RequestExt, Response, StatusCode};
+    use conduit_middleware::MiddlewareBuilder;
+    use conduit_test::MockRequest;
+
+    use super::{ConditionalWrite, Resource};
+
+    const NOW: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+    const BEFORE: &str = "Sat, 05 Nov 1994 08:49:37 GMT";
+
+    /// A stack around a handler counting its calls, and the loads of a
+    /// resource that exists if `etag` is set.
+    fn stack(
+        etag: Option<&'static str>,
+    ) -> (MiddlewareBuilder, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let load_counter = loads.clone();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let handler = move |_: &mut dyn RequestExt| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Response::builder().body(Body::empty())
+        };
+
+        let mut stack = MiddlewareBuilder::new(handler);
+        stack.around(ConditionalWrite::new(move |req: &dyn RequestExt| {
+            load_counter.fetch_add(1, Ordering::SeqCst);
+            if req.path() == "/error" {
+                return Err(box_error(std::io::Error::new(
+                    std::io::ErrorKind::Other,
+                    "database is down",
+                )));
+            }
+            Ok(etag.map(|etag| Resource {
+                etag: Some(etag.to_string()),
+                last_modified: Some(UNIX_EPOCH + Duration::from_secs(784_111_777)),
+            }))
+        }));
+        (stack, calls, loads)
+    }
+
+    fn status(
+        etag: Option<&'static str>,
+        method: Method,
+        headers: &[(header::HeaderName, &str)],
+    ) -> (StatusCode, usize) {
+        let (stack, calls, _) = stack(etag);
+        let mut req = MockRequest::new(method, "/");
+        for (name, value) in headers {
+            req.header(name.clone(), value);
+        }
+        let res = stack.call(&mut req).expect("No response");
+        (res.status(), calls.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn if_match() {
+        let ok = (StatusCode::OK, 1);
+        let failed = (StatusCode::PRECONDITION_FAILED, 0);
+
+        let etag = Some("\"v2\"");
+        for method in [Method::PUT, Method::PATCH, Method::DELETE, Method::POST] {
+            let matching = [(header::IF_MATCH, "\"v1\", \"v2\"")];
+            assert_eq!(status(etag, method.clone(), &matching), ok);
+            let stale = [(header::IF_MATCH, "\"v1\"")];
+            assert_eq!(status(etag, method.clone(), &stale), failed);
+            assert_eq!(status(etag, method, &[]), ok);
+        }
+
+        assert_eq!(status(etag, Method::PUT, &[(header::IF_MATCH, "*")]), ok);
+        assert_eq!(
+            status(None, Method::PUT, &[(header::IF_MATCH, "*")]),
+            failed
+        );
+        assert_eq!(
+            status(
+                Some("W/\"v2\""),
+                Method::PUT,
+                &[(header::IF_MATCH, "W/\"v2\"")]
+            ),
+            failed
+        );
+    }
+
+    #[test]
+    fn if_unmodified_since() {
+        let etag = Some("\"v2\"");
+        assert_eq!(
+            status(etag, Method::PUT, &[(header::IF_UNMODIFIED_SINCE, NOW)]),
+            (StatusCode::OK, 1)
+        );
+        assert_eq!(
+            status(etag, Method::PUT, &[(header::IF_UNMODIFIED_SINCE, BEFORE)]),
+            (StatusCode::PRECONDITION_FAILED, 0)
+        );
+        assert_eq!(
+            status(None, Method::PUT, &[(header::IF_UNMODIFIED_SINCE, BEFORE)]),
+            (StatusCode::OK, 1)
+        );
+    }
+
+    #[test]
+    fn if_none_match() {
+        // Create the resource only if it doesn't exist yet
+        let create = [(header::IF_NONE_MATCH, "*")];
+        assert_eq!(status(None, Method::PUT, &create), (StatusCode::OK, 1));
+        assert_eq!(
+            status(Some("\"v1\""), Method::PUT, &create),
+            (StatusCode::PRECONDITION_FAILED, 0)
+        );
+    }
+
+    #[test]
+    fn safe_methods() {
+        for method in [Method::GET, Method::HEAD, Method::OPTIONS] {
+            let stale = [(header::IF_MATCH, "\"v1\"")];
+            assert_eq!(status(Some("\"v2\""), method, &stale), (StatusCode::OK, 1));
+        }
+    }
+
+    #[test]
+    fn loads_only_with_preconditions() {
+        let (stack, calls, loads) = stack(Some("\"v2\""));
+        let mut req = MockRequest::new(Method::PUT, "/");
+        req.header(header::IF_MODIFIED_SINCE, BEFORE);
+        let res = stack.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(loads.load(Ordering::SeqCst), 0);
+
+        for name in [
+            header::IF_MATCH,
+            header::IF_UNMODIFIED_SINCE,
+            header::IF_NONE_MATCH,
+        ] {
+            let mut req = MockRequest::new(Method::PUT, "/");
+            req.header(name, "*");
+            stack.call(&mut req).expect("No response");
+        }
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn load_error() {
+        let (stack, calls, _) = stack(Some("\"v2\""));
+        let mut req = MockRequest::new(Method::PUT, "/error");
+        req.header(header::IF_MATCH, "\"v2\"");
+        let err = stack.call(&mut req).err().unwrap();
+        assert_eq!(err.to_string(), "database is down");
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,500 @@
+#![warn(rust_2018_idioms)]
+//! Parsing and formatting of the dates of HTTP headers such as `Date`,
+//! `Last-Modified` or `If-Modified-Since`, see RFC 7231 §7.1.1.1.
+//!
+//! Dates are always formatted as IMF-fixdate, while the obsolete RFC 850
+//! and asctime formats are accepted when parsing:
+//!
+//! ```
+//! use std::time::{Duration, UNIX_EPOCH};
+//! use conduit_httpdate::{fmt_http_date, parse_http_date};
+//!
+//! let time = UNIX_EPOCH + Duration::from_secs(784_111_777);
+//! assert_eq!(fmt_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+//! assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), Ok(time));
+//! assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), Ok(time));
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const DAY_NAMES: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const LONG_DAY_NAMES: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// `0000-01-01 00:00:00`, the earliest date with a 4-digit year.
+const MIN_SECS: i64 = -62_167_219_200;
+/// `9999-12-31 23:59:59`, the latest date with a 4-digit year.
+const MAX_SECS: i64 = 253_402_300_799;
+
+/// A date of an HTTP header, with a precision of one second.
+///
+/// `Display` formats it as IMF-fixdate and `FromStr` accepts all three
+/// formats of RFC 7231. Times outside of the years 0 to 9999 are clamped,
+/// since they can't be formatted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HttpDate {
+    /// Seconds since the Unix epoch.
+    secs: i64,
+}
+
+/// The error returned when a string isn't a valid HTTP date.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidHttpDate;
+
+impl fmt::Display for InvalidHttpDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("invalid HTTP date")
+    }
+}
+
+impl Error for InvalidHttpDate {}
+
+/// Parses a date in any of the formats of RFC 7231.
+pub fn parse_http_date(s: &str) -> Result<SystemTime, InvalidHttpDate> {
+    s.parse::<HttpDate>().map(SystemTime::from)
+}
+
+/// Formats a time as IMF-fixdate, such as `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn fmt_http_date(time: SystemTime) -> String {
+    HttpDate::from(time).to_string()
+}
+
+impl HttpDate {
+    fn from_secs(secs: i64) -> HttpDate {
+        HttpDate {
+            secs: secs.clamp(MIN_SECS, MAX_SECS),
+        }
+    }
+
+    /// The date from its fields, if they are in range.
+    fn from_fields(year: i64, month: i64, day: i64, (h, m, s): (i64, i64, i64)) -> Option<Self> {
+        let valid = (0..=9999).contains(&year)
+            && (1..=12).contains(&month)
+            && (1..=days_in_month(year, month)).contains(&day)
+            && h < 24
+            && m < 60
+            // A leap second is allowed by the grammar.
+            && s <= 60;
+        if !valid {
+            return None;
+        }
+        let secs = days_from_civil(year, month, day) * 86_400 + h * 3_600 + m * 60 + s;
+        Some(HttpDate::from_secs(secs))
+    }
+
+    fn year(self) -> i64 {
+        civil_from_days(self.secs.div_euclid(86_400)).0
+    }
+}
+
+impl From<SystemTime> for HttpDate {
+    fn from(time: SystemTime) -> HttpDate {
+        let secs = match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs().min(MAX_SECS as u64) as i64,
+            Err(err) => {
+                // Round towards the past, like for later times.
+                let before = err.duration();
+                let secs = before.as_secs() + u64::from(before.subsec_nanos() > 0);
+                -(secs.min(-MIN_SECS as u64) as i64)
+            }
+        };
+        HttpDate::from_secs(secs)
+    }
+}
+
+impl From<HttpDate> for SystemTime {
+    fn from(date: HttpDate) -> SystemTime {
+        let secs = Duration::from_secs(date.secs.unsigned_abs());
+        if date.secs >= 0 {
+            UNIX_EPOCH + secs
+        } else {
+            UNIX_EPOCH - secs
+        }
+    }
+}
+
+impl fmt::Display for HttpDate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days = self.secs.div_euclid(86_400);
+        let secs = self.secs.rem_euclid(86_400);
+        let (year, month, day) = civil_from_days(days);
+        write!(
+            f,
+            "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            DAY_NAMES[weekday(days)],
+            day,
+            MONTHS[month as usize - 1],
+            year,
+            secs / 3_600,
+            secs / 60 % 60,
+            secs % 60,
+        )
+    }
+}
+
+impl FromStr for HttpDate {
+    type Err = InvalidHttpDate;
+
+    /// Parses an IMF-fixdate, RFC 850 or asctime date.
+    ///
+    /// The two-digit years of RFC 850 dates are interpreted as the closest
+    /// year that isn't more than 50 years in the future. The day of the week
+    /// is required, but not checked against the date.
+    fn from_str(s: &str) -> Result<HttpDate, InvalidHttpDate> {
+        let s = s.as_bytes();
+        parse_imf_fixdate(s)
+            .or_else(|| parse_rfc850(s, HttpDate::from(SystemTime::now()).year()))
+            .or_else(|| parse_asctime(s))
+            .ok_or(InvalidHttpDate)
+    }
+}
+
+/// `Sun, 06 Nov 1994 08:49:37 GMT`
+fn parse_imf_fixdate(s: &[u8]) -> Option<HttpDate> {
+    if s.len() != 29 || &s[3..5] != b", " || s[7] != b' ' || s[11] != b' ' || s[16] != b' ' {
+        return None;
+    }
+    if &s[25..] != b" GMT" {
+        return None;
+    }
+    position(&DAY_NAMES, &s[..3])?;
+    let day = number(&s[5..7])?;
+    let month = position(&MONTHS, &s[8..11])? + 1;
+    let year = number(&s[12..16])?;
+    HttpDate::from_fields(year, month, day, time_of_day(&s[17..25])?)
+}
+
+/// `Sunday, 06-Nov-94 08:49:37 GMT`
+fn parse_rfc850(s: &[u8], current_year: i64) -> Option<HttpDate> {
+    let comma = s.iter().position(|&b| b == b',')?;
+    position(&LONG_DAY_NAMES, &s[..comma])?;
+    let s = &s[comma + 1..];
+    if s.len() != 23 || s[0] != b' ' || s[3] != b'-' || s[7] != b'-' || s[10] != b' ' {
+        return None;
+    }
+    if &s[19..] != b" GMT" {
+        return None;
+    }
+    let day = number(&s[1..3])?;
+    let month = position(&MONTHS, &s[4..7])? + 1;
+    let year = full_year(number(&s[8..10])?, current_year);
+    HttpDate::from_fields(year, month, day, time_of_day(&s[11..19])?)
+}
+
+/// `Sun Nov  6 08:49:37 1994`
+fn parse_asctime(s: &[u8]) -> Option<HttpDate> {
+    if s.len() != 24 || s[3] != b' ' || s[7] != b' ' || s[10] != b' ' || s[19] != b' ' {
+        return None;
+    }
+    position(&DAY_NAMES, &s[..3])?;
+    let month = position(&MONTHS, &s[4..7])? + 1;
+    let day = match &s[8..10] {
+        [b' ', digit] => number(&[*digit])?,
+        digits => number(digits)?,
+    };
+    let year = number(&s[20..24])?;
+    HttpDate::from_fields(year, month, day, time_of_day(&s[11..19])?)
+}
+
+/// `08:49:37`
+fn time_of_day(s: &[u8]) -> Option<(i64, i64, i64)> {
+    if s[2] != b':' || s[5] != b':' {
+        return None;
+    }
+    Some((number(&s[..2])?, number(&s[3..5])?, number(&s[6..])?))
+}
+
+/// A number of ASCII digits.
+fn number(s: &[u8]) -> Option<i64> {
+    s.iter().try_fold(0, |n, &b| {
+        if b.is_ascii_digit() {
+            Some(n * 10 + i64::from(b - b'0'))
+        } else {
+            None
+        }
+    })
+}
+
+fn position(names: &[&str], s: &[u8]) -> Option<i64> {
+    names
+        .iter()
+        .position(|name| name.as_bytes() == s)
+        .map(|i| i as i64)
+}
+
+/// The year of a two-digit year that is at most 50 years after
+/// `current_year`, as required by RFC 7231.
+fn full_year(year: i64, current_year: i64) -> i64 {
+    let year = current_year - current_year % 100 + year;
+    if year > current_year + 50 {
+        year - 100
+    } else if year + 100 <= current_year + 50 {
+        year + 100
+    } else {
+        year
+    }
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The index in `DAY_NAMES` of a number of days since the Unix epoch, which
+/// was a Thursday.
+fn weekday(days: i64) -> usize {
+    (days + 4).rem_euclid(7) as usize
+}
+
+/// The number of days since the Unix epoch of a date of the proleptic
+/// Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // Count from March, so that leap days end the year.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The inverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use super::{
+        civil_from_days, fmt_http_date, parse_http_date, parse_rfc850, weekday, HttpDate,
+        InvalidHttpDate, DAY_NAMES, LONG_DAY_NAMES, MAX_SECS, MIN_SECS, MONTHS,
+    };
+
+    const EXAMPLE: &str = "Sun, 06 Nov 1994 08:49:37 GMT";
+
+    fn time(secs: i64) -> SystemTime {
+        HttpDate { secs }.into()
+    }
+
+    /// A xorshift generator, to cover many dates reproducibly.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn secs(&mut self) -> i64 {
+            MIN_SECS + (self.next() % (MAX_SECS - MIN_SECS + 1) as u64) as i64
+        }
+    }
+
+    /// A date in the obsolete formats, which are only parsed.
+    fn fmt_obsolete(secs: i64) -> (String, String) {
+        let days = secs.div_euclid(86_400);
+        let time = secs.rem_euclid(86_400);
+        let (h, m, s) = (time / 3_600, time / 60 % 60, time % 60);
+        let (year, month, day) = civil_from_days(days);
+        let rfc850 = format!(
+            "{}, {:02}-{}-{:02} {:02}:{:02}:{:02} GMT",
+            LONG_DAY_NAMES[weekday(days)],
+            day,
+            MONTHS[month as usize - 1],
+            year % 100,
+            h,
+            m,
+            s,
+        );
+        let asctime = format!(
+            "{} {} {:>2} {:02}:{:02}:{:02} {:04}",
+            DAY_NAMES[weekday(days)],
+            MONTHS[month as usize - 1],
+            day,
+            h,
+            m,
+            s,
+            year,
+        );
+        (rfc850, asctime)
+    }
+
+    #[test]
+    fn rfc7231_examples() {
+        let time = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        assert_eq!(fmt_http_date(time), EXAMPLE);
+        assert_eq!(parse_http_date(EXAMPLE), Ok(time));
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), Ok(time));
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), Ok(time));
+        assert_eq!(parse_http_date("Sun Nov 06 08:49:37 1994"), Ok(time));
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(fmt_http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        let subsec = UNIX_EPOCH + Duration::from_millis(1_999);
+        assert_eq!(fmt_http_date(subsec), "Thu, 01 Jan 1970 00:00:01 GMT");
+        let before = UNIX_EPOCH - Duration::from_millis(500);
+        assert_eq!(fmt_http_date(before), "Wed, 31 Dec 1969 23:59:59 GMT");
+        assert_eq!(
+            fmt_http_date(time(951_782_400)),
+            "Tue, 29 Feb 2000 00:00:00 GMT"
+        );
+        assert_eq!(
+            fmt_http_date(time(MIN_SECS)),
+            "Sat, 01 Jan 0000 00:00:00 GMT"
+        );
+        assert_eq!(
+            fmt_http_date(time(MAX_SECS)),
+            "Fri, 31 Dec 9999 23:59:59 GMT"
+        );
+
+        let far = UNIX_EPOCH + Duration::from_secs(MAX_SECS as u64 * 2);
+        assert_eq!(fmt_http_date(far), "Fri, 31 Dec 9999 23:59:59 GMT");
+    }
+
+    #[test]
+    fn invalid_dates() {
+        for date in &[
+            "",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 06 Nov 1994 08:49:37 GMT ",
+            "sun, 06 Nov 1994 08:49:37 GMT",
+            "Sun, 06 nov 1994 08:49:37 GMT",
+            "Sun, 6 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 94 08:49:37 GMT",
+            "Sun, 06 Nov 1994 8:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:60:00 GMT",
+            "Sun, 31 Nov 1994 08:49:37 GMT",
+            "Sun, 00 Nov 1994 08:49:37 GMT",
+            "Sun, 29 Feb 1900 08:49:37 GMT",
+            "Sun, +6 Nov 1994 08:49:37 GMT",
+            "Sunday, 06 Nov 1994 08:49:37 GMT",
+            "Sun, 06-Nov-94 08:49:37 GMT",
+            "Sunday, 06-Nov-1994 08:49:37 GMT",
+            "Sun Nov 6 08:49:37 1994",
+            "Sun Nov  6 08:49:37 94",
+            "1994-11-06 08:49:37 +0000",
+            "Sön, 06 Nov 1994 08:49:37 GMT",
+        ] {
+            assert_eq!(parse_http_date(date), Err(InvalidHttpDate), "{}", date);
+        }
+    }
+
+    #[test]
+    fn leap_days_and_seconds() {
+        assert!(parse_http_date("Tue, 29 Feb 2000 00:00:00 GMT").is_ok());
+        assert!(parse_http_date("Thu, 29 Feb 2024 00:00:00 GMT").is_ok());
+        assert!(parse_http_date("Wed, 29 Feb 2023 00:00:00 GMT").is_err());
+        assert_eq!(
+            parse_http_date("Sat, 31 Dec 2016 23:59:60 GMT"),
+            parse_http_date("Sun, 01 Jan 2017 00:00:00 GMT"),
+        );
+    }
+
+    #[test]
+    fn rfc850_years() {
+        let year = |date, current| {
+            let date = parse_rfc850(date, current).map(HttpDate::year);
+            date.expect("invalid date")
+        };
+        assert_eq!(year(b"Sunday, 06-Nov-94 08:49:37 GMT", 2026), 1994);
+        assert_eq!(year(b"Sunday, 06-Nov-76 08:49:37 GMT", 2026), 2076);
+        assert_eq!(year(b"Sunday, 06-Nov-77 08:49:37 GMT", 2026), 1977);
+        assert_eq!(year(b"Sunday, 06-Nov-00 08:49:37 GMT", 2026), 2000);
+        assert_eq!(year(b"Sunday, 06-Nov-10 08:49:37 GMT", 2090), 2110);
+        assert_eq!(year(b"Sunday, 06-Nov-41 08:49:37 GMT", 2090), 2041);
+        assert_eq!(year(b"Sunday, 06-Nov-41 08:49:37 GMT", 2091), 2141);
+        assert_eq!(year(b"Sunday, 06-Nov-42 08:49:37 GMT", 2091), 2042);
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        let current_year = HttpDate::from(SystemTime::now()).year();
+        for _ in 0..20_000 {
+            let secs = rng.secs();
+            let date = HttpDate { secs };
+            let formatted = date.to_string();
+            assert_eq!(formatted.len(), 29, "{}", formatted);
+            assert_eq!(formatted.parse(), Ok(date), "{}", formatted);
+
+            let (rfc850, asctime) = fmt_obsolete(secs);
+            assert_eq!(asctime.parse(), Ok(date), "{}", asctime);
+            let parsed: HttpDate = rfc850.parse().expect(&rfc850);
+            assert_eq!(parsed.year() % 100, date.year() % 100, "{}", rfc850);
+            if (current_year - 49..=current_year + 50).contains(&date.year()) {
+                assert_eq!(parsed, date, "{}", rfc850);
+            }
+
+            let time = SystemTime::from(date);
+            assert_eq!(HttpDate::from(time), date);
+        }
+    }
+
+    #[test]
+    fn mutated_dates() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..20_000 {
+            let secs = rng.secs();
+            let (rfc850, asctime) = fmt_obsolete(secs);
+            let formatted = HttpDate { secs }.to_string();
+            let mut bytes = match rng.next() % 3 {
+                0 => formatted.into_bytes(),
+                1 => rfc850.into_bytes(),
+                _ => asctime.into_bytes(),
+            };
+            for _ in 0..=rng.next() % 3 {
+                let i = rng.next() as usize % bytes.len();
+                match rng.next() % 3 {
+                    0 => bytes[i] = rng.next() as u8,
+                    1 => bytes.insert(i, b"0 :-,"[rng.next() as usize % 5]),
+                    _ => drop(bytes.remove(i)),
+                }
+            }
+
+            // Mutations may still produce valid dates, which then format
+            // back to the same date.
+            let mutated = String::from_utf8_lossy(&bytes);
+            if let Ok(date) = mutated.parse::<HttpDate>() {
+                assert_eq!(date.to_string().parse(), Ok(date), "{}", mutated);
+            }
+        }
+    }
+}
@@ -1,4 +1,6 @@
 #![warn(rust_2018_idioms)]
+use std::sync::Arc;
+
 use conduit::{BoxError, Handler, RequestExt};
 
 pub type BeforeResult = Result<(), BoxError>;
@@ -14,6 +16,17 @@ pub trait Middleware: Send + Sync + 'static {
     }
 }
 
+/// Shared middleware, such as one instance used in several stacks.
+impl<M: Middleware + ?Sized> Middleware for Arc<M> {
+    fn before(&self, req: &mut dyn RequestExt) -> BeforeResult {
+        (**self).before(req)
+    }
+
+    fn after(&self, req: &mut dyn RequestExt, res: AfterResult) -> AfterResult {
+        (**self).after(req, res)
+    }
+}
+
 pub trait AroundMiddleware: Handler {
     fn with_handler(&mut self, handler: Box<dyn Handler>);
 }
@@ -91,6 +104,7 @@ mod tests {
     use std::io;
     use std::io::prelude::*;
     use std::net::SocketAddr;
+    use std::sync::Arc;
 
     use conduit_test::ResponseExt;
 
@@ -163,7 +177,7 @@ mod tests {
     struct MyMiddleware;
 
     impl Middleware for MyMiddleware {
-        fn before<'a>(&self, req: &'a mut dyn RequestExt) -> BeforeResult {
+        fn before(&self, req: &mut dyn RequestExt) -> BeforeResult {
             req.mut_extensions().insert("hello".to_string());
             Ok(())
         }
@@ -286,6 +300,19 @@ mod tests {
         assert_eq!(*res.into_cow(), b"Error in handler"[..]);
     }
 
+    #[test]
+    fn test_shared_middleware() {
+        let shared: Arc<dyn Middleware> = Arc::new(MyMiddleware);
+        let mut builder = MiddlewareBuilder::new(handler);
+        builder.add(shared.clone());
+
+        let mut req = RequestSentinel::new(Method::GET, "/");
+        let res = builder.call(&mut req).expect("No response");
+
+        assert_eq!(*res.into_cow(), b"hello"[..]);
+        assert_eq!(Arc::strong_count(&shared), 2);
+    }
+
     #[test]
     fn test_around_middleware() {
         let mut builder = MiddlewareBuilder::new(middle_handler);
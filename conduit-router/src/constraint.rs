@@ -0,0 +1,264 @@
+use std::fmt;
+use std::sync::Arc;
+
+use conduit::header::{self, HeaderName, HeaderValue};
+use conduit::{Host, RequestExt};
+use regex::Regex;
+use route_recognizer::Params;
+
+/// A restriction on the value of a route parameter.
+///
+/// Routes whose constraints aren't met don't match, so that the request is
+/// routed to the next matching route, if any.
+#[derive(Clone)]
+pub enum Constraint {
+    /// One or more ASCII digits.
+    Numeric,
+    /// A UUID in its hyphenated form, such as
+    /// `67e55044-10b1-426f-9247-bb680e5fe0c8`, in either case.
+    Uuid,
+    /// A regular expression matching the value, see `Constraint::regex` to
+    /// match the whole value.
+    Regex(Regex),
+    /// A custom predicate on the value.
+    Custom(Arc<dyn Fn(&str) -> bool + Send + Sync>),
+}
+
+impl Constraint {
+    /// A constraint matching values that are entirely matched by `pattern`.
+    pub fn regex(pattern: &str) -> Result<Constraint, regex::Error> {
+        Regex::new(&format!("^(?:{})$", pattern)).map(Constraint::Regex)
+    }
+
+    pub fn custom<F>(predicate: F) -> Constraint
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        Constraint::Custom(Arc::new(predicate))
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            Constraint::Numeric => !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()),
+            Constraint::Uuid => is_uuid(value),
+            Constraint::Regex(regex) => regex.is_match(value),
+            Constraint::Custom(predicate) => predicate(value),
+        }
+    }
+
+    /// Whether both constraints match the same values. Custom constraints
+    /// can't be compared and are never the same.
+    fn same_as(&self, other: &Constraint) -> bool {
+        match (self, other) {
+            (Constraint::Numeric, Constraint::Numeric) => true,
+            (Constraint::Uuid, Constraint::Uuid) => true,
+            (Constraint::Regex(regex), Constraint::Regex(other)) => {
+                regex.as_str() == other.as_str()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::Numeric => f.write_str("Numeric"),
+            Constraint::Uuid => f.write_str("Uuid"),
+            Constraint::Regex(regex) => f.debug_tuple("Regex").field(regex).finish(),
+            Constraint::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+fn is_uuid(value: &str) -> bool {
+    let groups: Vec<_> = value.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(group, len)| group.len() == len && group.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+type RequestPredicate = dyn Fn(&dyn RequestExt) -> bool + Send + Sync;
+
+/// A condition on attributes of the request other than its path.
+#[derive(Clone)]
+pub(crate) enum Matcher {
+    Host(String),
+    Header(HeaderName, HeaderValue),
+    Accept(String),
+    Custom(Arc<RequestPredicate>),
+}
+
+impl Matcher {
+    fn matches(&self, request: &dyn RequestExt) -> bool {
+        match self {
+            Matcher::Host(host) => match request.host() {
+                Host::Name(name) => strip_port(name).eq_ignore_ascii_case(host),
+                Host::Socket(_) => false,
+            },
+            Matcher::Header(name, value) => {
+                request.headers().get_all(name).iter().any(|v| v == value)
+            }
+            Matcher::Accept(media_type) => accepts(request, media_type),
+            Matcher::Custom(predicate) => predicate(request),
+        }
+    }
+
+    /// Whether both matchers match the same requests. Custom matchers can't
+    /// be compared and are never the same.
+    fn same_as(&self, other: &Matcher) -> bool {
+        match (self, other) {
+            (Matcher::Host(host), Matcher::Host(other)) => host.eq_ignore_ascii_case(other),
+            (Matcher::Header(name, value), Matcher::Header(other_name, other_value)) => {
+                name == other_name && value == other_value
+            }
+            (Matcher::Accept(media_type), Matcher::Accept(other)) => {
+                media_type.eq_ignore_ascii_case(other)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn strip_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        // An IPv6 address, such as `[::1]:8080`
+        return host.find(']').map_or(host, |end| &host[..=end]);
+    }
+    host.rsplit_once(':').map_or(host, |(name, _)| name)
+}
+
+/// Whether the `Accept` header of the request allows `media_type`, such as
+/// `application/json`. Requests without the header accept any type.
+fn accepts(request: &dyn RequestExt, media_type: &str) -> bool {
+    let mut ranges = request
+        .headers()
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .peekable();
+    if ranges.peek().is_none() {
+        return true;
+    }
+
+    let (kind, subtype) = media_type.split_once('/').unwrap_or((media_type, ""));
+    ranges.any(|range| {
+        let mut params = range.split(';');
+        let range = params.next().unwrap_or("").trim();
+        let rejected = params
+            .filter_map(|param| param.split_once('='))
+            .any(|(name, q)| {
+                name.trim().eq_ignore_ascii_case("q") && q.trim().parse::<f32>() == Ok(0.0)
+            });
+        let (range_kind, range_subtype) = range.split_once('/').unwrap_or((range, ""));
+        !rejected
+            && (range_kind == "*" || range_kind.eq_ignore_ascii_case(kind))
+            && (range_subtype == "*" || range_subtype.eq_ignore_ascii_case(subtype))
+    })
+}
+
+/// The constraints and matchers of a route.
+#[derive(Clone, Default)]
+pub(crate) struct Conditions {
+    pub(crate) constraints: Vec<(String, Constraint)>,
+    pub(crate) matchers: Vec<Matcher>,
+}
+
+impl Conditions {
+    pub(crate) fn len(&self) -> usize {
+        self.constraints.len() + self.matchers.len()
+    }
+
+    /// Whether these conditions, of a route with the parameters `names`, are
+    /// the same as `other`, of a route of the same shape with the parameters
+    /// `other_names`. Constraints are compared by the position of their
+    /// parameter, as the names may differ.
+    pub(crate) fn same_as(
+        &self,
+        names: &[String],
+        other: &Conditions,
+        other_names: &[String],
+    ) -> bool {
+        let constraints = self.positioned_constraints(names);
+        let other_constraints = other.positioned_constraints(other_names);
+
+        same_set(&constraints, &other_constraints, |(i, a), (j, b)| {
+            i == j && a.same_as(b)
+        }) && same_set(&self.matchers, &other.matchers, Matcher::same_as)
+    }
+
+    fn positioned_constraints(&self, names: &[String]) -> Vec<(Option<usize>, &Constraint)> {
+        self.constraints
+            .iter()
+            .map(|(name, constraint)| (names.iter().position(|n| n == name), constraint))
+            .collect()
+    }
+
+    pub(crate) fn matches(&self, params: &Params, request: &dyn RequestExt) -> bool {
+        self.constraints.iter().all(|(name, constraint)| {
+            params
+                .find(name)
+                .map_or(false, |value| constraint.matches(value))
+        }) && self.matchers.iter().all(|matcher| matcher.matches(request))
+    }
+}
+
+/// Whether every element of `a` has an equal one in `b`, and the other way
+/// around.
+fn same_set<T>(a: &[T], b: &[T], eq: impl Fn(&T, &T) -> bool) -> bool {
+    a.iter().all(|x| b.iter().any(|y| eq(x, y))) && b.iter().all(|y| a.iter().any(|x| eq(x, y)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Constraint;
+
+    #[test]
+    fn numeric() {
+        assert!(Constraint::Numeric.matches("0123"));
+        assert!(!Constraint::Numeric.matches(""));
+        assert!(!Constraint::Numeric.matches("12a"));
+        assert!(!Constraint::Numeric.matches("-1"));
+    }
+
+    #[test]
+    fn uuid() {
+        assert!(Constraint::Uuid.matches("67e55044-10b1-426f-9247-bb680e5fe0c8"));
+        assert!(Constraint::Uuid.matches("67E55044-10B1-426F-9247-BB680E5FE0C8"));
+        assert!(!Constraint::Uuid.matches("67e5504410b1426f9247bb680e5fe0c8"));
+        assert!(!Constraint::Uuid.matches("67e55044-10b1-426f-9247-bb680e5fe0c"));
+        assert!(!Constraint::Uuid.matches("g7e55044-10b1-426f-9247-bb680e5fe0c8"));
+    }
+
+    #[test]
+    fn regex() {
+        let constraint = Constraint::regex("[a-z]+|[0-9]{4}").unwrap();
+        assert!(constraint.matches("hello"));
+        assert!(constraint.matches("2024"));
+        assert!(!constraint.matches("hello2024"));
+        assert!(!constraint.matches("202"));
+        assert!(Constraint::regex("(").is_err());
+    }
+
+    #[test]
+    fn custom() {
+        let constraint = Constraint::custom(|value| value.len() == 2);
+        assert!(constraint.matches("ab"));
+        assert!(!constraint.matches("abc"));
+    }
+
+    #[test]
+    fn same_as() {
+        let regex = Constraint::regex("[a-z]+").unwrap();
+        assert!(regex.same_as(&Constraint::regex("[a-z]+").unwrap()));
+        assert!(!regex.same_as(&Constraint::regex("[a-z]*").unwrap()));
+        assert!(Constraint::Numeric.same_as(&Constraint::Numeric));
+        assert!(!Constraint::Numeric.same_as(&Constraint::Uuid));
+
+        let custom = Constraint::custom(|_| true);
+        assert!(!custom.same_as(&custom.clone()));
+    }
+}
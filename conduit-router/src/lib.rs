@@ -3,27 +3,97 @@
 #[macro_use]
 extern crate tracing;
 
-use std::collections::hash_map::{Entry, HashMap};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
 
-use conduit::{box_error, Handler, HandlerResult, Method, RequestExt};
-use route_recognizer::{Match, Params, Router};
+use conduit::{
+    box_error, header, Body, Handler, HandlerResult, Method, RequestExt, Response, StatusCode,
+};
+use route_recognizer::Params;
+use serde::Deserialize;
+
+use crate::constraint::Conditions;
+use crate::mount::{MountPrefix, MountedRequest};
+use crate::normalize::Normalization;
+use crate::table::{RouteEntry, Table};
+
+pub use crate::constraint::Constraint;
+pub use crate::normalize::PathPolicy;
+pub use crate::params::ParamError;
+pub use crate::route::{Group, Route};
+pub use crate::table::{RouteInfo, RouteTable};
+pub use crate::urls::{UrlError, Urls};
+
+mod constraint;
+mod mount;
+mod normalize;
+mod params;
+mod route;
+mod table;
+mod urls;
 
 #[derive(Default)]
 pub struct RouteBuilder {
-    routers: HashMap<Method, Router<WrappedHandler>>,
+    table: Table,
+    /// Mounted handlers, sorted by decreasing length of their prefix.
+    mounts: Vec<Mount>,
+    /// The patterns of named routes, by name.
+    names: Arc<HashMap<String, String>>,
+    normalization: Normalization,
+    not_found: Option<Box<dyn Handler>>,
+    method_not_allowed: Option<Box<dyn Handler>>,
 }
 
-#[derive(Clone, Copy)]
-pub struct RoutePattern(&'static str);
+/// The pattern of the route that matched a request, including the prefixes
+/// of the mounts the request was dispatched through.
+///
+/// The pattern is shared with the router, so that inserting it into the
+/// request extensions doesn't allocate. Routers join the prefixes of the
+/// routers they are mounted in into their patterns when mounted; only the
+/// prefixes of mounts of other handlers, such as a router wrapped in
+/// middleware, are joined per request.
+#[derive(Clone, Debug)]
+pub struct RoutePattern(Arc<str>);
 
 impl RoutePattern {
     pub fn pattern(&self) -> &str {
-        self.0
+        &self.0
+    }
+
+    fn mounted(&self, prefix: &str) -> RoutePattern {
+        if &*self.0 == "/" {
+            RoutePattern(prefix.into())
+        } else {
+            RoutePattern(format!("{}{}", prefix, self.0).into())
+        }
     }
 }
 
+struct Mount {
+    prefix: Box<str>,
+    /// The prefix joined with the prefixes of the routers this router is
+    /// mounted in.
+    joined: Arc<str>,
+    /// The pattern reported for requests to the mount, such as `/api/*`.
+    pattern: RoutePattern,
+    handler: MountHandler,
+}
+
+enum MountHandler {
+    /// A router, whose patterns include the joined prefix of the mount.
+    Router(Box<RouteBuilder>),
+    Handler(Box<dyn Handler>),
+}
+
 struct WrappedHandler {
+    /// The pattern of the route, as registered.
     pattern: RoutePattern,
+    /// The pattern reported to the handler, including the joined prefixes
+    /// of the routers this router is mounted in.
+    mounted: RoutePattern,
     handler: Box<dyn Handler>,
 }
 
@@ -39,115 +109,583 @@ pub enum RouterError {
     UnknownMethod,
     #[error("Path not found")]
     PathNotFound,
+    /// The path matches routes of other methods only, listed in the order
+    /// they should appear in an `Allow` header.
+    #[error("Method not allowed")]
+    MethodNotAllowed(Vec<Method>),
+}
+
+impl RouterError {
+    /// The status code of the response a server should send for this error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            RouterError::UnknownMethod | RouterError::PathNotFound => StatusCode::NOT_FOUND,
+            RouterError::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+        }
+    }
+
+    /// The value of the `Allow` header of a `405 Method Not Allowed`
+    /// response, such as `GET, POST`.
+    pub fn allow_header(&self) -> Option<String> {
+        match self {
+            RouterError::MethodNotAllowed(methods) => Some(allow_header(methods)),
+            _ => None,
+        }
+    }
 }
 
 impl RouteBuilder {
     pub fn new() -> Self {
         Self {
-            routers: HashMap::new(),
+            table: Table::default(),
+            mounts: Vec::new(),
+            names: Arc::default(),
+            normalization: Normalization::default(),
+            not_found: None,
+            method_not_allowed: None,
         }
     }
 
-    #[instrument(level = "trace", skip(self))]
+    #[instrument(level = "trace", skip(self, request))]
     fn recognize<'a>(
         &'a self,
         method: &Method,
-        path: &str,
-    ) -> Result<Match<&WrappedHandler>, RouterError> {
-        match self.routers.get(method) {
-            Some(router) => router.recognize(path).or(Err(RouterError::PathNotFound)),
-            None => Err(RouterError::UnknownMethod),
+        request: &dyn RequestExt,
+    ) -> Result<(&'a WrappedHandler, Params), RouterError> {
+        if let Some((route, params)) = self.table.find(method, request.path(), request) {
+            return Ok((&route.handler, params));
+        }
+
+        let allowed = self.allowed_methods(request);
+        if !allowed.is_empty() {
+            Err(RouterError::MethodNotAllowed(allowed))
+        } else if self.table.contains_method(method) {
+            Err(RouterError::PathNotFound)
+        } else {
+            Err(RouterError::UnknownMethod)
         }
     }
 
-    #[instrument(level = "trace", skip(self, handler))]
+    /// The methods with a route matching the request, sorted by name.
+    ///
+    /// `HEAD` is allowed for paths with a `GET` route and `OPTIONS` for any
+    /// path with a route, as they are answered automatically.
+    fn allowed_methods(&self, request: &dyn RequestExt) -> Vec<Method> {
+        let mut allowed: Vec<_> = self
+            .table
+            .methods()
+            .filter(|method| self.table.find(method, request.path(), request).is_some())
+            .cloned()
+            .collect();
+        if allowed.is_empty() {
+            return allowed;
+        }
+        if allowed.contains(&Method::GET) {
+            allowed.push(Method::HEAD);
+        }
+        allowed.push(Method::OPTIONS);
+        allowed.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        allowed.dedup();
+        allowed
+    }
+
+    #[instrument(level = "trace", skip(self, pattern, handler), fields(pattern))]
     pub fn map<H: Handler>(
         &mut self,
         method: Method,
-        pattern: &'static str,
+        pattern: impl Into<Arc<str>>,
         handler: H,
     ) -> &mut Self {
-        let router = match self.routers.entry(method) {
-            Entry::Occupied(e) => e.into_mut(),
-            Entry::Vacant(e) => e.insert(Router::new()),
+        let pattern = pattern.into();
+        tracing::Span::current().record("pattern", &*pattern);
+        self.route(method, pattern).to(handler)
+    }
+
+    /// Start registering a route, to configure it before setting its
+    /// handler:
+    ///
+    /// ```
+    /// # use conduit::{Body, Method, RequestExt, Response};
+    /// # use conduit_router::{Constraint, RouteBuilder};
+    /// # fn show(_: &mut dyn RequestExt) -> conduit::HttpResult {
+    /// #     Response::builder().body(Body::empty())
+    /// # }
+    /// let mut router = RouteBuilder::new();
+    /// router.route(Method::GET, "/posts/:id").name("post").to(show);
+    /// router
+    ///     .route(Method::GET, "/users/:id")
+    ///     .constraint("id", Constraint::Numeric)
+    ///     .accept("application/json")
+    ///     .to(show);
+    /// ```
+    ///
+    /// When several routes match a request, the one with the most static
+    /// segments is used, then the one with the most dynamic segments rather
+    /// than globs, then the one with the most constraints and matchers. Ties
+    /// go to the route registered first. Routes whose constraints or
+    /// matchers fail are skipped, so that `/posts/new` and a numeric
+    /// `/posts/:id` don't collide.
+    ///
+    /// Patterns can be borrowed or owned strings, such as patterns loaded from
+    /// a configuration file.
+    pub fn route(&mut self, method: Method, pattern: impl Into<Arc<str>>) -> Route<'_> {
+        Route::new(self, method, pattern.into(), Vec::new())
+    }
+
+    /// Group routes under a common path prefix, such as `/admin`, to share
+    /// middleware that only runs for those routes:
+    ///
+    /// ```
+    /// # use conduit::{Body, RequestExt, Response};
+    /// # use conduit_middleware::Middleware;
+    /// # use conduit_router::RouteBuilder;
+    /// # struct Auth;
+    /// # impl Middleware for Auth {}
+    /// # fn dashboard(_: &mut dyn RequestExt) -> conduit::HttpResult {
+    /// #     Response::builder().body(Body::empty())
+    /// # }
+    /// let mut router = RouteBuilder::new();
+    /// router
+    ///     .group("/admin")
+    ///     .middleware(Auth)
+    ///     .get("/dashboard", dashboard);
+    /// ```
+    ///
+    /// Unlike mounting, the prefix stays part of the path and of the route
+    /// pattern.
+    pub fn group(&mut self, prefix: &str) -> Group<'_> {
+        Group::new(self, prefix)
+    }
+
+    fn add(
+        &mut self,
+        method: Method,
+        pattern: Arc<str>,
+        name: Option<String>,
+        conditions: Conditions,
+        handler: Box<dyn Handler>,
+    ) {
+        if let Some(name) = &name {
+            let names = Arc::make_mut(&mut self.names);
+            if let Some(other) = names.get(name) {
+                assert_eq!(
+                    *other, *pattern,
+                    "route name {:?} is already used for another pattern",
+                    name
+                );
+            }
+            names.insert(name.clone(), pattern.to_string());
+        }
+
+        let handler = WrappedHandler {
+            pattern: RoutePattern(pattern.clone()),
+            mounted: RoutePattern(pattern.clone()),
+            handler,
+        };
+        let entry = RouteEntry::new(&pattern, name, conditions, handler);
+        self.table.add(method, entry);
+    }
+
+    /// How to handle requests for a path matching a route once a trailing
+    /// slash is removed or added, such as `/posts/1/` for `/posts/:id`.
+    pub fn trailing_slash(&mut self, policy: PathPolicy) -> &mut Self {
+        self.normalization.trailing_slash = policy;
+        self
+    }
+
+    /// How to handle requests for a path matching a route when ignoring
+    /// the ASCII case of its static segments, such as `/Posts/Hello` for
+    /// `/posts/:slug`.
+    ///
+    /// The case of parameters is kept when normalizing the path.
+    pub fn case_insensitive(&mut self, policy: PathPolicy) -> &mut Self {
+        self.normalization.case = policy;
+        self
+    }
+
+    /// How to handle requests for a path matching a route once runs of
+    /// slashes are merged, such as `/posts//1` for `/posts/:id`.
+    pub fn duplicate_slashes(&mut self, policy: PathPolicy) -> &mut Self {
+        self.normalization.duplicate_slashes = policy;
+        self
+    }
+
+    /// Handle requests matching no route with `handler`, instead of
+    /// returning a `RouterError::PathNotFound` or
+    /// `RouterError::UnknownMethod` error.
+    ///
+    /// The error is available to the handler in the request extensions, see
+    /// `RouterError::status` for the status of the response.
+    pub fn not_found<H: Handler>(&mut self, handler: H) -> &mut Self {
+        self.not_found = Some(Box::new(handler));
+        self
+    }
+
+    /// Handle requests for a path only matching routes of other methods with
+    /// `handler`, instead of returning a `RouterError::MethodNotAllowed`
+    /// error.
+    ///
+    /// The error is available to the handler in the request extensions, and
+    /// the `Allow` header is added to the response unless the handler set
+    /// it.
+    pub fn method_not_allowed<H: Handler>(&mut self, handler: H) -> &mut Self {
+        self.method_not_allowed = Some(Box::new(handler));
+        self
+    }
+
+    /// The routes registered with this router, sorted by pattern and method.
+    ///
+    /// The routes of handlers added with `RouteBuilder::mount` aren't
+    /// included.
+    pub fn routes(&self) -> Vec<RouteInfo<'_>> {
+        self.table.routes()
+    }
+
+    /// The routes of this router as a table, for debugging or
+    /// documentation:
+    ///
+    /// ```
+    /// # use conduit::{Body, Method, RequestExt, Response};
+    /// # use conduit_router::RouteBuilder;
+    /// # fn handler(_: &mut dyn RequestExt) -> conduit::HttpResult {
+    /// #     Response::builder().body(Body::empty())
+    /// # }
+    /// let mut router = RouteBuilder::new();
+    /// router.get("/posts", handler);
+    /// router.route(Method::GET, "/posts/:id").name("post").to(handler);
+    /// router.delete("/posts/:id", handler);
+    ///
+    /// assert_eq!(
+    ///     router.route_table().to_string(),
+    ///     "GET     /posts\n\
+    ///      DELETE  /posts/:id\n\
+    ///      GET     /posts/:id  post\n"
+    /// );
+    /// ```
+    pub fn route_table(&self) -> RouteTable<'_> {
+        RouteTable(self.routes())
+    }
+
+    /// The URLs of the named routes, relative to the root of this router.
+    pub fn urls(&self) -> Urls {
+        Urls::new(self.names.clone(), "")
+    }
+
+    /// Mount a handler, such as another `RouteBuilder`, under a path prefix
+    /// like `/api/v1`.
+    ///
+    /// Requests for the prefix or a path below it are passed to the handler,
+    /// with the prefix moved from `RequestExt::path` to
+    /// `RequestExt::virtual_root`. Mounts take precedence over routes, and
+    /// the mount with the longest matching prefix is used. Routers report
+    /// their patterns with the prefix included, such as `/api/v1/posts/:id`.
+    #[instrument(level = "trace", skip(self, handler))]
+    pub fn mount<H: Handler>(&mut self, prefix: &str, handler: H) -> &mut Self {
+        let prefix = prefix.trim_end_matches('/');
+        assert!(
+            prefix.is_empty() || prefix.starts_with('/'),
+            "mount prefix must start with a slash: {:?}",
+            prefix
+        );
+        let index = self
+            .mounts
+            .iter()
+            .position(|mount| mount.prefix.len() < prefix.len())
+            .unwrap_or(self.mounts.len());
+        let handler: Box<dyn Any> = Box::new(handler);
+        let handler = match handler.downcast::<RouteBuilder>() {
+            Ok(mut router) => {
+                router.add_prefix(prefix);
+                MountHandler::Router(router)
+            }
+            Err(handler) => MountHandler::Handler(handler.downcast::<H>().unwrap()),
         };
-        let wrapped_handler = WrappedHandler {
-            pattern: RoutePattern(pattern),
-            handler: Box::new(handler),
+        let mount = Mount {
+            prefix: prefix.into(),
+            joined: prefix.into(),
+            pattern: RoutePattern(format!("{}/*", prefix).into()),
+            handler,
         };
-        router.add(pattern, wrapped_handler);
+        self.mounts.insert(index, mount);
         self
     }
 
-    pub fn get<H: Handler>(&mut self, pattern: &'static str, handler: H) -> &mut Self {
+    /// Join `prefix` into the patterns reported for the routes and mounts of
+    /// this router, when mounting it under `prefix`.
+    fn add_prefix(&mut self, prefix: &str) {
+        for handler in self.table.handlers_mut() {
+            handler.mounted = handler.mounted.mounted(prefix);
+        }
+        for mount in &mut self.mounts {
+            mount.joined = format!("{}{}", prefix, mount.joined).into();
+            mount.pattern = mount.pattern.mounted(prefix);
+            if let MountHandler::Router(router) = &mut mount.handler {
+                router.add_prefix(prefix);
+            }
+        }
+    }
+
+    pub fn get<H: Handler>(&mut self, pattern: impl Into<Arc<str>>, handler: H) -> &mut Self {
         self.map(Method::GET, pattern, handler)
     }
 
-    pub fn post<H: Handler>(&mut self, pattern: &'static str, handler: H) -> &mut Self {
+    pub fn post<H: Handler>(&mut self, pattern: impl Into<Arc<str>>, handler: H) -> &mut Self {
         self.map(Method::POST, pattern, handler)
     }
 
-    pub fn put<H: Handler>(&mut self, pattern: &'static str, handler: H) -> &mut Self {
+    pub fn put<H: Handler>(&mut self, pattern: impl Into<Arc<str>>, handler: H) -> &mut Self {
         self.map(Method::PUT, pattern, handler)
     }
 
-    pub fn delete<H: Handler>(&mut self, pattern: &'static str, handler: H) -> &mut Self {
+    pub fn delete<H: Handler>(&mut self, pattern: impl Into<Arc<str>>, handler: H) -> &mut Self {
         self.map(Method::DELETE, pattern, handler)
     }
 
-    pub fn head<H: Handler>(&mut self, pattern: &'static str, handler: H) -> &mut Self {
+    /// Register a `HEAD` route.
+    ///
+    /// Without one, `HEAD` requests are answered by the `GET` route of the
+    /// path, with the body of its response removed.
+    pub fn head<H: Handler>(&mut self, pattern: impl Into<Arc<str>>, handler: H) -> &mut Self {
         self.map(Method::HEAD, pattern, handler)
     }
+
+    /// Register an `OPTIONS` route.
+    ///
+    /// Without one, `OPTIONS` requests are answered with `204 No Content`
+    /// and an `Allow` header listing the methods of the path.
+    pub fn options<H: Handler>(&mut self, pattern: impl Into<Arc<str>>, handler: H) -> &mut Self {
+        self.map(Method::OPTIONS, pattern, handler)
+    }
 }
 
 impl conduit::Handler for RouteBuilder {
     #[instrument(level = "trace", skip(self, request))]
     fn call(&self, request: &mut dyn RequestExt) -> HandlerResult {
-        let mut m = {
+        let path = request.path();
+        if let Some(mount) = self.mounts.iter().find(|m| mount::matches(&m.prefix, path)) {
+            return call_mounted(mount, request);
+        }
+
+        let ((handler, params), head) = {
             let method = request.method();
-            let path = request.path();
 
-            match self.recognize(method, path) {
+            let result = match self.recognize(method, request) {
+                Err(RouterError::MethodNotAllowed(allowed))
+                    if *method == Method::HEAD && allowed.contains(&Method::GET) =>
+                {
+                    debug!("answering HEAD request with GET route");
+                    self.recognize(&Method::GET, request).map(|m| (m, true))
+                }
+                Err(RouterError::MethodNotAllowed(allowed)) if *method == Method::OPTIONS => {
+                    debug!("answering OPTIONS request");
+                    return Ok(options_response(&allowed));
+                }
+                result => result.map(|m| (m, false)),
+            };
+
+            match result {
                 Ok(m) => m,
-                Err(e) => {
-                    info!("{}", e);
-                    return Err(box_error(e));
+                Err(e @ (RouterError::PathNotFound | RouterError::UnknownMethod)) => {
+                    if let Some((path, policy)) = self.normalization.normalize(&self.table, request)
+                    {
+                        return self.call_normalized(request, path, policy);
+                    }
+                    return self.call_fallback(request, e);
                 }
+                Err(e) => return self.call_fallback(request, e),
             }
         };
 
-        // We don't have `pub` access to the fields to destructure `Params`, so swap with an empty
-        // value to avoid an allocation.
-        let mut params = Params::new();
-        std::mem::swap(m.params_mut(), &mut params);
-
-        let pattern = m.handler().pattern;
-        debug!(pattern = pattern.0, "matching route handler found");
+        let pattern = match request.extensions().get::<MountPrefix>() {
+            Some(MountPrefix(prefix)) => handler.mounted.mounted(prefix),
+            None => handler.mounted.clone(),
+        };
+        debug!(pattern = pattern.pattern(), "matching route handler found");
 
+        let span = trace_span!("handler", pattern = pattern.pattern());
+        let urls = Urls::new(self.names.clone(), request.virtual_root().unwrap_or(""));
         let extensions = request.mut_extensions();
+        extensions.insert(urls);
         extensions.insert(pattern);
         extensions.insert(params);
 
-        let span = trace_span!("handler", pattern = pattern.0);
-        span.in_scope(|| m.handler().call(request))
+        let response = span.in_scope(|| handler.call(request))?;
+        if head {
+            Ok(strip_body(response))
+        } else {
+            Ok(response)
+        }
+    }
+}
+
+impl RouteBuilder {
+    /// Handle a request matching no route with the fallback handler for
+    /// `error`, if any.
+    #[instrument(level = "trace", skip(self, request))]
+    fn call_fallback(&self, request: &mut dyn RequestExt, error: RouterError) -> HandlerResult {
+        info!("{}", error);
+        let handler = match error {
+            RouterError::MethodNotAllowed(_) => &self.method_not_allowed,
+            RouterError::UnknownMethod | RouterError::PathNotFound => &self.not_found,
+        };
+        let handler = match handler {
+            Some(handler) => handler,
+            None => return Err(box_error(error)),
+        };
+
+        let allow = error.allow_header();
+        let urls = Urls::new(self.names.clone(), request.virtual_root().unwrap_or(""));
+        let extensions = request.mut_extensions();
+        extensions.insert(urls);
+        extensions.insert(error);
+
+        let mut response = handler.call(request)?;
+        if let Some(allow) = allow {
+            let allow = header::HeaderValue::from_str(&allow).map_err(box_error)?;
+            response.headers_mut().entry(header::ALLOW).or_insert(allow);
+        }
+        Ok(response)
+    }
+
+    #[instrument(level = "trace", skip(self, request))]
+    fn call_normalized(
+        &self,
+        request: &mut dyn RequestExt,
+        path: String,
+        policy: PathPolicy,
+    ) -> HandlerResult {
+        if let Some(status) = policy.redirect_status() {
+            debug!("redirecting to normalized path");
+            return normalize::redirect(request, &path, status).map_err(box_error);
+        }
+
+        debug!("routing normalized path");
+        let original = std::mem::replace(request.path_mut(), path);
+        let result = self.call(request);
+        *request.path_mut() = original;
+        result
+    }
+}
+
+#[instrument(level = "trace", skip(mount, request), fields(prefix = &*mount.prefix))]
+fn call_mounted(mount: &Mount, request: &mut dyn RequestExt) -> HandlerResult {
+    let outer = request.extensions().get::<MountPrefix>().cloned();
+    let (prefix, pattern) = match &outer {
+        Some(MountPrefix(outer)) => (
+            format!("{}{}", outer, mount.joined).into(),
+            mount.pattern.mounted(outer),
+        ),
+        None => (mount.joined.clone(), mount.pattern.clone()),
+    };
+    request.mut_extensions().insert(pattern);
+
+    let handler = match &mount.handler {
+        MountHandler::Router(router) => {
+            return router.call(&mut MountedRequest::new(request, &mount.prefix));
+        }
+        MountHandler::Handler(handler) => handler,
+    };
+
+    request.mut_extensions().insert(MountPrefix(prefix));
+    let result = handler.call(&mut MountedRequest::new(request, &mount.prefix));
+
+    let extensions = request.mut_extensions();
+    match outer {
+        Some(outer) => extensions.insert(outer),
+        None => extensions.remove::<MountPrefix>(),
+    };
+    result
+}
+
+fn allow_header(methods: &[Method]) -> String {
+    methods
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn options_response(allowed: &[Method]) -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header(header::ALLOW, allow_header(allowed))
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Remove the body of a response to a `GET` request, to answer a `HEAD`
+/// request, keeping the `Content-Length` of the body.
+fn strip_body(response: Response<Body>) -> Response<Body> {
+    let (mut parts, body) = response.into_parts();
+    if !parts.headers.contains_key(header::CONTENT_LENGTH) {
+        let length = match &body {
+            Body::Static(bytes) => Some(bytes.len() as u64),
+            Body::Owned(bytes) => Some(bytes.len() as u64),
+            Body::File(file) => file.metadata().ok().map(|data| data.len()),
+            Body::Stream(_) => None,
+        };
+        if let Some(length) = length {
+            parts.headers.insert(header::CONTENT_LENGTH, length.into());
+        }
     }
+    Response::from_parts(parts, Body::empty())
 }
 
 pub trait RequestParams<'a> {
+    /// The raw parameters of the matched route, empty if the request wasn't
+    /// dispatched by a router.
     fn params(self) -> &'a Params;
+
+    /// Percent-decode and parse the parameter `name`, such as `id` in
+    /// `/posts/:id`.
+    fn param<T>(self, name: &str) -> Result<T, ParamError>
+    where
+        T: FromStr,
+        T::Err: Display;
+
+    /// Deserialize all parameters into `T`, usually a struct with a field per
+    /// parameter.
+    ///
+    /// Values are percent-decoded and parsed into the type of the field, such
+    /// as numbers, `bool` or unit enum variants. `&str` fields borrow from the
+    /// request, so use `String` or `Cow<str>` for values that may be escaped.
+    fn params_as<T: Deserialize<'a>>(self) -> Result<T, ParamError>;
 }
 
 impl<'a> RequestParams<'a> for &'a (dyn RequestExt + 'a) {
     fn params(self) -> &'a Params {
-        self.extensions().get::<Params>().expect("Missing params")
+        lazy_static::lazy_static! {
+            static ref EMPTY: Params = Params::new();
+        }
+        match self.extensions().get::<Params>() {
+            Some(params) => params,
+            None => &EMPTY,
+        }
+    }
+
+    fn param<T>(self, name: &str) -> Result<T, ParamError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        params::parse(self.params(), name)
+    }
+
+    fn params_as<T: Deserialize<'a>>(self) -> Result<T, ParamError> {
+        params::deserialize(self.params())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{RequestParams, RouteBuilder, RoutePattern};
+    use conduit_middleware::{AfterResult, BeforeResult, Middleware, MiddlewareBuilder};
+
+    use super::{
+        Constraint, ParamError, PathPolicy, RequestParams, RouteBuilder, RoutePattern, RouterError,
+        UrlError, Urls,
+    };
 
-    use conduit::{Body, Handler, Method, Response, StatusCode};
+    use conduit::{box_error, header, Body, Handler, Method, RequestExt, Response, StatusCode};
     use conduit_test::{MockRequest, ResponseExt};
 
     lazy_static::lazy_static! {
@@ -200,34 +738,961 @@ mod tests {
         lazy_static::initialize(&TRACING);
 
         let router = test_router();
-        let mut req = MockRequest::new(Method::DELETE, "/posts/1");
+        let mut req = MockRequest::new(Method::DELETE, "/nonexistent");
         let err = router.call(&mut req).err().unwrap();
 
         assert_eq!(err.to_string(), "Invalid method");
     }
 
     #[test]
-    fn catch_all() {
+    fn method_not_allowed() {
         lazy_static::initialize(&TRACING);
 
-        let mut router = RouteBuilder::new();
-        router.get("/*", test_handler);
+        let mut router = test_router();
+        router.delete("/comments/:id", test_handler);
 
-        let mut req = MockRequest::new(Method::GET, "/foo");
+        for method in [Method::DELETE, Method::PUT] {
+            let mut req = MockRequest::new(method, "/posts/1");
+            let err = router.call(&mut req).err().unwrap();
+            let err = err.downcast_ref::<RouterError>().unwrap();
+
+            assert_eq!(err.to_string(), "Method not allowed");
+            let allowed = [Method::GET, Method::HEAD, Method::OPTIONS, Method::POST];
+            assert!(matches!(err, RouterError::MethodNotAllowed(methods) if methods == &allowed));
+            assert_eq!(err.status(), StatusCode::METHOD_NOT_ALLOWED);
+            assert_eq!(
+                err.allow_header().as_deref(),
+                Some("GET, HEAD, OPTIONS, POST")
+            );
+        }
+
+        let mut req = MockRequest::new(Method::DELETE, "/posts");
+        let err = router.call(&mut req).err().unwrap();
+        let err = err.downcast_ref::<RouterError>().unwrap();
+        assert!(matches!(err, RouterError::PathNotFound));
+        assert_eq!(err.allow_header(), None);
+    }
+
+    #[test]
+    fn automatic_head() {
+        lazy_static::initialize(&TRACING);
+
+        let router = test_router();
+        let mut req = MockRequest::new(Method::HEAD, "/posts/1");
         let res = router.call(&mut req).expect("No response");
+
         assert_eq!(res.status(), StatusCode::OK);
-        assert_eq!(*res.into_cow(), b", GET, /*"[..]);
+        // The GET handler runs, seeing the original method
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "19");
+        assert_eq!(*res.into_cow(), b""[..]);
     }
 
-    fn test_router() -> RouteBuilder {
+    #[test]
+    fn explicit_head() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = test_router();
+        router.head("/posts/:id", test_handler);
+        let mut req = MockRequest::new(Method::HEAD, "/posts/1");
+        let res = router.call(&mut req).expect("No response");
+
+        assert_eq!(*res.into_cow(), b"1, HEAD, /posts/:id"[..]);
+    }
+
+    #[test]
+    fn automatic_options() {
+        lazy_static::initialize(&TRACING);
+
+        let router = test_router();
+        let mut req = MockRequest::new(Method::OPTIONS, "/posts/1");
+        let res = router.call(&mut req).expect("No response");
+
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers()[header::ALLOW], "GET, HEAD, OPTIONS, POST");
+
+        let mut req = MockRequest::new(Method::OPTIONS, "/nonexistent");
+        let err = router.call(&mut req).err().unwrap();
+        assert_eq!(err.to_string(), "Invalid method");
+    }
+
+    #[test]
+    fn explicit_options() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = test_router();
+        router.options("/posts/:id", test_handler);
+        let mut req = MockRequest::new(Method::OPTIONS, "/posts/1");
+        let res = router.call(&mut req).expect("No response");
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(*res.into_cow(), b"1, OPTIONS, /posts/:id"[..]);
+    }
+
+    #[test]
+    fn mounted_router() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = RouteBuilder::new();
+        router.mount("/api/v1", test_router());
+
+        let mut req = MockRequest::new(Method::GET, "/api/v1/posts/1");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"1, GET, /api/v1/posts/:id"[..]);
+
+        let mut req = MockRequest::new(Method::GET, "/api/v1/nonexistent");
+        let err = router.call(&mut req).err().unwrap();
+        assert_eq!(err.to_string(), "Path not found");
+
+        let mut req = MockRequest::new(Method::GET, "/posts/1");
+        let err = router.call(&mut req).err().unwrap();
+        assert_eq!(err.to_string(), "Invalid method");
+    }
+
+    #[test]
+    fn mounted_handler() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = RouteBuilder::new();
+        router.get("/apiary", test_handler);
+        router.mount("/api/", mount_handler);
+        router.mount("/api/v2", test_router());
+
+        let mut req = MockRequest::new(Method::GET, "/api/posts");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"/api, /posts, /api/*"[..]);
+        // Changes to the path by the mounted handler don't leak out
+        assert_eq!(req.path(), "/api/posts");
+
+        let mut req = MockRequest::new(Method::GET, "/api");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"/api, /, /api/*"[..]);
+
+        let mut req = MockRequest::new(Method::GET, "/apiary");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b", GET, /apiary"[..]);
+
+        // The longest prefix wins
+        let mut req = MockRequest::new(Method::POST, "/api/v2/posts/3");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"3, POST, /api/v2/posts/:id"[..]);
+    }
+
+    #[test]
+    fn nested_mounts() {
+        lazy_static::initialize(&TRACING);
+
+        let mut inner = RouteBuilder::new();
+        inner.mount("/posts", mount_handler);
+        inner.get("/", test_handler);
+        let mut outer = RouteBuilder::new();
+        outer.mount("/api", inner);
+
+        let mut req = MockRequest::new(Method::GET, "/api/posts/1");
+        let res = outer.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"/api/posts, /1, /api/posts/*"[..]);
+
+        let mut req = MockRequest::new(Method::GET, "/api");
+        let res = outer.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b", GET, /api"[..]);
+    }
+
+    #[test]
+    fn wrapped_mounts() {
+        lazy_static::initialize(&TRACING);
+
+        let mut posts = RouteBuilder::new();
+        posts.get("/:id", test_handler);
+        let mut api = RouteBuilder::new();
+        api.mount("/posts", MiddlewareBuilder::new(posts));
+        let mut outer = RouteBuilder::new();
+        outer.mount("/v1", api);
+
+        let mut req = MockRequest::new(Method::GET, "/v1/posts/1");
+        let res = outer.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"1, GET, /v1/posts/:id"[..]);
+    }
+
+    #[test]
+    fn typed_params() {
+        lazy_static::initialize(&TRACING);
+
+        fn handler(req: &mut dyn conduit::RequestExt) -> Result<Response<Body>, ParamError> {
+            let id: u32 = req.param("id")?;
+            Ok(Response::new(Body::from_vec(
+                format!("{}", id + 1).into_bytes(),
+            )))
+        }
+
+        let mut router = RouteBuilder::new();
+        router.get("/posts/:id", handler);
+
+        let mut req = MockRequest::new(Method::GET, "/posts/41");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"42"[..]);
+
+        let mut req = MockRequest::new(Method::GET, "/posts/first");
+        let err = router.call(&mut req).err().unwrap();
+        let err = err.downcast_ref::<ParamError>().unwrap();
+        assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn params_without_router() {
+        let req = MockRequest::new(Method::GET, "/");
+        let req: &dyn RequestExt = &req;
+        assert_eq!(req.params().iter().count(), 0);
+        assert_eq!(
+            req.param::<u32>("id"),
+            Err(ParamError::Missing("id".into()))
+        );
+    }
+
+    #[test]
+    fn named_routes() {
+        lazy_static::initialize(&TRACING);
+
+        fn handler(req: &mut dyn conduit::RequestExt) -> Result<Response<Body>, UrlError> {
+            let urls = req.extensions().get::<Urls>().unwrap();
+            let url = urls.url_for("post", &[("id", "a b")])?;
+            Ok(Response::new(Body::from_vec(url.into_bytes())))
+        }
+
+        let mut api = RouteBuilder::new();
+        api.route(Method::GET, "/posts/:id")
+            .name("post")
+            .to(handler);
+        assert_eq!(
+            api.urls().url_for("post", &[("id", "1")]).unwrap(),
+            "/posts/1"
+        );
+
+        let mut req = MockRequest::new(Method::GET, "/posts/1");
+        let res = api.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"/posts/a%20b"[..]);
+
+        let mut router = RouteBuilder::new();
+        router.mount("/api", api);
+        let mut req = MockRequest::new(Method::GET, "/api/posts/1");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"/api/posts/a%20b"[..]);
+    }
+
+    #[test]
+    fn url_params_round_trip() {
+        lazy_static::initialize(&TRACING);
+
+        fn handler(req: &mut dyn conduit::RequestExt) -> Result<Response<Body>, ParamError> {
+            let name: String = req.param("name")?;
+            let path: String = req.param("path")?;
+            Ok(Response::new(Body::from_vec(
+                format!("{}|{}", name, path).into_bytes(),
+            )))
+        }
+
+        let mut router = RouteBuilder::new();
+        router
+            .route(Method::GET, "/users/:name/files/*path")
+            .name("file")
+            .to(handler);
+
+        let name = "a b/c%d?é";
+        let path = "docs/read me.txt";
+        let url = router
+            .urls()
+            .url_for("file", &[("name", name), ("path", path)])
+            .unwrap();
+        let mut req = MockRequest::new(Method::GET, &url);
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(
+            *res.into_cow(),
+            format!("{}|{}", name, path).into_bytes()[..]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "already used")]
+    fn duplicate_route_name() {
         let mut router = RouteBuilder::new();
-        router.post("/posts/:id", test_handler);
-        router.get("/posts/:id", test_handler);
         router
+            .route(Method::GET, "/posts")
+            .name("posts")
+            .to(test_handler);
+        router
+            .route(Method::POST, "/posts")
+            .name("posts")
+            .to(test_handler);
+        router
+            .route(Method::GET, "/users")
+            .name("posts")
+            .to(test_handler);
+    }
+
+    #[test]
+    fn dynamic_patterns() {
+        lazy_static::initialize(&TRACING);
+
+        let mut api = RouteBuilder::new();
+        for resource in ["posts", "users"] {
+            api.get(format!("/{}/:id", resource), test_handler);
+        }
+        let config = String::from("/comments/:id");
+        api.route(Method::GET, config.as_str()).to(test_handler);
+        drop(config);
+
+        let mut router = RouteBuilder::new();
+        router.mount(&format!("/api/v{}", 2), api);
+
+        let mut req = MockRequest::new(Method::GET, "/api/v2/users/3");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"3, GET, /api/v2/users/:id"[..]);
+
+        let mut req = MockRequest::new(Method::GET, "/api/v2/comments/4");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"4, GET, /api/v2/comments/:id"[..]);
+    }
+
+    fn fallback_handler(req: &mut dyn conduit::RequestExt) -> conduit::HttpResult {
+        let err = req.extensions().get::<RouterError>().unwrap();
+        let body = format!("{} {}", err, req.path()).into_bytes();
+        Response::builder()
+            .status(err.status())
+            .body(Body::from_vec(body))
+    }
+
+    #[test]
+    fn not_found_handler() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = test_router();
+        router.not_found(fallback_handler);
+
+        for (method, body) in [
+            (Method::GET, &b"Path not found /nonexistent"[..]),
+            (Method::DELETE, &b"Invalid method /nonexistent"[..]),
+        ] {
+            let mut req = MockRequest::new(method, "/nonexistent");
+            let res = router.call(&mut req).expect("No response");
+            assert_eq!(res.status(), StatusCode::NOT_FOUND);
+            assert_eq!(*res.into_cow(), *body);
+        }
+
+        // Other errors are still returned
+        let mut req = MockRequest::new(Method::PUT, "/posts/1");
+        let err = router.call(&mut req).err().unwrap();
+        assert_eq!(err.to_string(), "Method not allowed");
+    }
+
+    #[test]
+    fn method_not_allowed_handler() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = test_router();
+        router.method_not_allowed(fallback_handler);
+
+        let mut req = MockRequest::new(Method::PUT, "/posts/1");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers()[header::ALLOW], "GET, HEAD, OPTIONS, POST");
+        assert_eq!(*res.into_cow(), b"Method not allowed /posts/1"[..]);
+
+        router.method_not_allowed(|_: &mut dyn RequestExt| {
+            Response::builder()
+                .status(StatusCode::METHOD_NOT_ALLOWED)
+                .header(header::ALLOW, "GET")
+                .body(Body::empty())
+        });
+        let mut req = MockRequest::new(Method::PUT, "/posts/1");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(res.headers()[header::ALLOW], "GET");
+
+        let mut req = MockRequest::new(Method::GET, "/nonexistent");
+        let err = router.call(&mut req).err().unwrap();
+        assert_eq!(err.to_string(), "Path not found");
+    }
+
+    fn location(res: &Response<Body>) -> &str {
+        res.headers()[header::LOCATION].to_str().unwrap()
+    }
+
+    #[test]
+    fn strict_paths() {
+        lazy_static::initialize(&TRACING);
+
+        let router = test_router();
+        for path in ["/posts/1/", "/Posts/1", "/posts//1"] {
+            let mut req = MockRequest::new(Method::GET, path);
+            let err = router.call(&mut req).err().unwrap();
+            assert_eq!(err.to_string(), "Path not found");
+        }
+    }
+
+    #[test]
+    fn trailing_slash_redirect() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = test_router();
+        router.get("/about/", test_handler);
+        router.trailing_slash(PathPolicy::MovedPermanently);
+
+        let mut req = MockRequest::new(Method::GET, "/posts/1/");
+        req.with_query("page=2");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(location(&res), "/posts/1?page=2");
+
+        let mut req = MockRequest::new(Method::GET, "/about");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(location(&res), "/about/");
+
+        router.trailing_slash(PathPolicy::PermanentRedirect);
+        let mut req = MockRequest::new(Method::POST, "/posts/1/");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(location(&res), "/posts/1");
+
+        let mut req = MockRequest::new(Method::GET, "/");
+        let err = router.call(&mut req).err().unwrap();
+        assert_eq!(err.to_string(), "Path not found");
+    }
+
+    #[test]
+    fn trailing_slash_transparent() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = test_router();
+        router.trailing_slash(PathPolicy::Transparent);
+
+        let mut req = MockRequest::new(Method::GET, "/posts/1/");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(*res.into_cow(), b"1, GET, /posts/:id"[..]);
+        assert_eq!(req.path(), "/posts/1/");
+
+        // The normalized path must match a route of some method
+        let mut req = MockRequest::new(Method::DELETE, "/posts/1/");
+        let err = router.call(&mut req).err().unwrap();
+        assert_eq!(err.to_string(), "Method not allowed");
+    }
+
+    #[test]
+    fn case_insensitive() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = test_router();
+        router.get("/posts/:id/Comments", test_handler);
+        router.case_insensitive(PathPolicy::MovedPermanently);
+
+        let mut req = MockRequest::new(Method::GET, "/POSTS/AbC/comments");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(location(&res), "/posts/AbC/Comments");
+
+        router.case_insensitive(PathPolicy::Transparent);
+        let mut req = MockRequest::new(Method::POST, "/Posts/AbC");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"AbC, POST, /posts/:id"[..]);
+    }
+
+    #[test]
+    fn case_insensitive_constraints() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = RouteBuilder::new();
+        router
+            .route(Method::GET, "/posts/:id")
+            .constraint("id", Constraint::regex("[a-z]+").unwrap())
+            .to(test_handler);
+        router.case_insensitive(PathPolicy::Transparent);
+
+        let mut req = MockRequest::new(Method::GET, "/POSTS/abc");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"abc, GET, /posts/:id"[..]);
+
+        let mut req = MockRequest::new(Method::GET, "/POSTS/ABC");
+        let err = router.call(&mut req).err().unwrap();
+        assert_eq!(err.to_string(), "Path not found");
+    }
+
+    #[test]
+    fn duplicate_slashes() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = test_router();
+        router.duplicate_slashes(PathPolicy::MovedPermanently);
+
+        let mut req = MockRequest::new(Method::GET, "//posts///1");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(location(&res), "/posts/1");
+
+        // Redirects take precedence over transparent normalization
+        router
+            .duplicate_slashes(PathPolicy::Transparent)
+            .trailing_slash(PathPolicy::PermanentRedirect)
+            .case_insensitive(PathPolicy::Transparent);
+        let mut req = MockRequest::new(Method::GET, "/Posts//1/");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(location(&res), "/posts/1");
+
+        router.trailing_slash(PathPolicy::Transparent);
+        let mut req = MockRequest::new(Method::GET, "/Posts//1/");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"1, GET, /posts/:id"[..]);
+    }
+
+    #[test]
+    fn mounted_normalization() {
+        lazy_static::initialize(&TRACING);
+
+        let mut api = test_router();
+        api.trailing_slash(PathPolicy::MovedPermanently);
+        let mut router = RouteBuilder::new();
+        router.mount("/api", api);
+
+        let mut req = MockRequest::new(Method::GET, "/api/posts/1/");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(location(&res), "/api/posts/1");
+    }
+
+    #[test]
+    fn list_routes() {
+        let mut router = RouteBuilder::new();
+        router.get("/posts/:id", test_handler);
+        router
+            .route(Method::POST, "/posts")
+            .name("create_post")
+            .to(test_handler);
+        router.get("/posts", test_handler);
+        router
+            .route(Method::GET, "/posts/:id")
+            .constraint("id", Constraint::Numeric)
+            .name("post")
+            .to(test_handler);
+        router.mount("/api", test_router());
+
+        let routes: Vec<_> = router
+            .routes()
+            .iter()
+            .map(|route| (route.method().clone(), route.pattern(), route.name()))
+            .collect();
+        assert_eq!(
+            routes,
+            [
+                (Method::GET, "/posts", None),
+                (Method::POST, "/posts", Some("create_post")),
+                (Method::GET, "/posts/:id", Some("post")),
+                (Method::GET, "/posts/:id", None),
+            ]
+        );
+
+        assert_eq!(
+            router.route_table().to_string(),
+            "GET   /posts\n\
+             POST  /posts      create_post\n\
+             GET   /posts/:id  post\n\
+             GET   /posts/:id\n"
+        );
+        assert_eq!(RouteBuilder::new().route_table().to_string(), "");
+    }
+
+    #[test]
+    #[should_panic(expected = "route GET /posts/:id is already registered")]
+    fn duplicate_route() {
+        let mut router = test_router();
+        router.get("/posts/:id", test_handler);
+    }
+
+    #[test]
+    #[should_panic(expected = "route GET posts/:slug is shadowed by GET /posts/:id")]
+    fn shadowed_route() {
+        let mut router = test_router();
+        router.get("posts/:slug", test_handler);
+    }
+
+    #[test]
+    fn routes_with_different_conditions() {
+        let mut router = test_router();
+        router
+            .route(Method::GET, "/posts/:slug")
+            .accept("text/html")
+            .to(test_handler);
+        router
+            .route(Method::GET, "/posts/:slug")
+            .accept("application/json")
+            .to(test_handler);
+        router
+            .route(Method::GET, "/posts/:slug")
+            .constraint("slug", Constraint::Numeric)
+            .accept("text/html")
+            .to(test_handler);
+        router.put("/posts/:id", test_handler);
+        assert_eq!(router.routes().len(), 6);
+    }
+
+    #[test]
+    #[should_panic(expected = "route GET /posts/:slug is already registered")]
+    fn overlapping_routes_with_conditions() {
+        let mut router = test_router();
+        router
+            .route(Method::GET, "/posts/:slug")
+            .accept("text/html")
+            .to(test_handler);
+        router
+            .route(Method::GET, "/posts/:slug")
+            .accept("text/html")
+            .to(test_handler);
+    }
+
+    #[test]
+    #[should_panic(expected = "route GET /posts/:slug is shadowed by GET /posts/:id")]
+    fn duplicate_routes_with_conditions() {
+        let mut router = test_router();
+        router
+            .route(Method::GET, "/posts/:id")
+            .constraint("id", Constraint::Numeric)
+            .accept("text/html")
+            .to(test_handler);
+        router
+            .route(Method::GET, "/posts/:slug")
+            .accept("TEXT/HTML")
+            .constraint("slug", Constraint::Numeric)
+            .to(test_handler);
+    }
+
+    /// Appends its name to the `Trace` extension before and after the
+    /// handler runs.
+    struct Tag(&'static str);
+
+    #[derive(Clone, Default)]
+    struct Trace(Vec<String>);
+
+    impl Middleware for Tag {
+        fn before(&self, req: &mut dyn RequestExt) -> BeforeResult {
+            let mut trace = req.extensions().get::<Trace>().cloned().unwrap_or_default();
+            trace.0.push(format!("before {}", self.0));
+            req.mut_extensions().insert(trace);
+            Ok(())
+        }
+
+        fn after(&self, req: &mut dyn RequestExt, res: AfterResult) -> AfterResult {
+            let mut trace = req.extensions().get::<Trace>().cloned().unwrap_or_default();
+            trace.0.push(format!("after {}", self.0));
+            req.mut_extensions().insert(trace);
+            res
+        }
+    }
+
+    struct Deny;
+
+    impl Middleware for Deny {
+        fn before(&self, _: &mut dyn RequestExt) -> BeforeResult {
+            Err(box_error(RouterError::PathNotFound))
+        }
+    }
+
+    fn trace(req: &MockRequest) -> Vec<String> {
+        req.extensions()
+            .get::<Trace>()
+            .map(|t| t.0.clone())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn route_middleware() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = RouteBuilder::new();
+        router
+            .route(Method::GET, "/private/:id")
+            .middleware(Tag("a"))
+            .middleware(Tag("b"))
+            .to(test_handler);
+        router.get("/public/:id", test_handler);
+
+        let mut req = MockRequest::new(Method::GET, "/private/1");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"1, GET, /private/:id"[..]);
+        assert_eq!(trace(&req), ["before a", "before b", "after b", "after a"]);
+
+        let mut req = MockRequest::new(Method::GET, "/public/1");
+        router.call(&mut req).expect("No response");
+        assert!(trace(&req).is_empty());
+    }
+
+    #[test]
+    fn group_middleware() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = RouteBuilder::new();
+        router.get("/", test_handler);
+        {
+            let mut admin = router.group("/admin/");
+            admin.middleware(Tag("admin"));
+            admin.get("/", test_handler);
+            admin
+                .route(Method::GET, "/posts/:id")
+                .middleware(Tag("post"))
+                .to(test_handler);
+            admin
+                .group("/secret")
+                .middleware(Deny)
+                .middleware(Tag("unreachable"))
+                .get("/:id", test_handler);
+        }
+
+        let mut req = MockRequest::new(Method::GET, "/");
+        router.call(&mut req).expect("No response");
+        assert!(trace(&req).is_empty());
+
+        let mut req = MockRequest::new(Method::GET, "/admin");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b", GET, /admin"[..]);
+        assert_eq!(trace(&req), ["before admin", "after admin"]);
+
+        let mut req = MockRequest::new(Method::GET, "/admin/posts/2");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"2, GET, /admin/posts/:id"[..]);
+        assert_eq!(
+            trace(&req),
+            ["before admin", "before post", "after post", "after admin"]
+        );
+
+        // An error in `before` skips the handler and the later middleware
+        let mut req = MockRequest::new(Method::GET, "/admin/secret/3");
+        let err = router.call(&mut req).err().unwrap();
+        assert_eq!(err.to_string(), "Path not found");
+        assert_eq!(trace(&req), ["before admin", "after admin"]);
+    }
+
+    #[test]
+    fn catch_all() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = RouteBuilder::new();
+        router.get("/*", test_handler);
+
+        let mut req = MockRequest::new(Method::GET, "/foo");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(*res.into_cow(), b", GET, /*"[..]);
+    }
+
+    #[test]
+    fn static_routes_win() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = RouteBuilder::new();
+        router
+            .route(Method::GET, "/posts/:id")
+            .constraint("id", Constraint::Numeric)
+            .to(tag("show"));
+        router.get("/posts/new", tag("new"));
+
+        assert_eq!(call(&router, "/posts/new"), "new");
+        assert_eq!(call(&router, "/posts/12"), "show");
+        let mut req = MockRequest::new(Method::GET, "/posts/newest");
+        let err = router.call(&mut req).err().unwrap();
+        assert_eq!(err.to_string(), "Path not found");
+    }
+
+    #[test]
+    fn constraints_fall_through() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = RouteBuilder::new();
+        router.get("/posts/*rest", tag("glob"));
+        router
+            .route(Method::GET, "/posts/:slug")
+            .constraint("slug", Constraint::regex("[a-z-]+").unwrap())
+            .to(tag("slug"));
+        router
+            .route(Method::GET, "/posts/:id")
+            .constraint("id", Constraint::Numeric)
+            .to(tag("id"));
+        router
+            .route(Method::GET, "/posts/:id")
+            .constraint("id", Constraint::Uuid)
+            .to(tag("uuid"));
+
+        assert_eq!(call(&router, "/posts/42"), "id");
+        assert_eq!(call(&router, "/posts/hello-world"), "slug");
+        assert_eq!(
+            call(&router, "/posts/67e55044-10b1-426f-9247-bb680e5fe0c8"),
+            "uuid"
+        );
+        assert_eq!(call(&router, "/posts/Hello"), "glob");
+        assert_eq!(call(&router, "/posts/1/comments"), "glob");
+    }
+
+    #[test]
+    fn constraint_params() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = RouteBuilder::new();
+        router
+            .route(Method::GET, "/posts/:id")
+            .constraint("id", Constraint::custom(|id| id.len() < 3))
+            .to(test_handler);
+
+        let mut req = MockRequest::new(Method::GET, "/posts/12");
+        let res = router.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"12, GET, /posts/:id"[..]);
+
+        let mut req = MockRequest::new(Method::GET, "/posts/123");
+        assert!(router.call(&mut req).is_err());
+    }
+
+    #[test]
+    fn shared_shape_params() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = RouteBuilder::new();
+        router
+            .route(Method::GET, "/posts/:slug")
+            .constraint("slug", Constraint::regex("[a-z]+").unwrap())
+            .to(|req: &mut dyn RequestExt| {
+                let slug = req.params().find("slug").unwrap_or("").to_string();
+                Response::builder().body(Body::from_vec(slug.into_bytes()))
+            });
+        router.get("/posts/:id", test_handler);
+
+        assert_eq!(call(&router, "/posts/hello"), "hello");
+        assert_eq!(call(&router, "/posts/12"), "12, GET, /posts/:id");
+    }
+
+    #[test]
+    #[should_panic(expected = "has no parameter \"slug\"")]
+    fn unknown_constraint_param() {
+        let mut router = RouteBuilder::new();
+        router
+            .route(Method::GET, "/posts/:id")
+            .constraint("slug", Constraint::Numeric)
+            .to(test_handler);
+    }
+
+    #[test]
+    fn matchers() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = RouteBuilder::new();
+        router.get("/posts", tag("any"));
+        router
+            .route(Method::GET, "/posts")
+            .host("api.example.com")
+            .to(tag("api"));
+        router
+            .route(Method::GET, "/posts")
+            .accept("application/json")
+            .to(tag("json"));
+        router
+            .route(Method::GET, "/posts")
+            .header(header::X_FRAME_OPTIONS, "DENY".parse().unwrap())
+            .to(tag("header"));
+        router
+            .route(Method::GET, "/posts")
+            .host("EXAMPLE.com")
+            .matcher(|req| req.query_string() == Some("draft"))
+            .to(tag("drafts"));
+
+        assert_eq!(call(&router, "/posts"), "json");
+
+        let mut req = MockRequest::new(Method::GET, "/posts");
+        req.header(header::ACCEPT, "text/html, application/*;q=0");
+        assert_eq!(call_with(&router, &mut req), "any");
+
+        req.header(header::X_FRAME_OPTIONS, "DENY");
+        assert_eq!(call_with(&router, &mut req), "header");
+
+        let mut req = MockRequest::new(Method::GET, "/posts");
+        req.header(header::ACCEPT, "text/*, application/json;q=0.5");
+        assert_eq!(call_with(&router, &mut req), "json");
+
+        // More conditions take precedence, whatever the registration order
+        req.with_query("draft");
+        assert_eq!(call_with(&router, &mut req), "drafts");
+    }
+
+    #[test]
+    fn registration_order() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = RouteBuilder::new();
+        router
+            .route(Method::GET, "/:a/:b")
+            .constraint("a", Constraint::Numeric)
+            .to(tag("first"));
+        router
+            .route(Method::GET, "/:a/:b")
+            .constraint("b", Constraint::Numeric)
+            .to(tag("second"));
+
+        assert_eq!(call(&router, "/1/2"), "first");
+        assert_eq!(call(&router, "/a/2"), "second");
+    }
+
+    #[test]
+    fn method_not_allowed_with_constraints() {
+        lazy_static::initialize(&TRACING);
+
+        let mut router = RouteBuilder::new();
+        router.get("/posts/:id", test_handler);
+        router
+            .route(Method::DELETE, "/posts/:id")
+            .constraint("id", Constraint::Numeric)
+            .to(test_handler);
+
+        let mut req = MockRequest::new(Method::POST, "/posts/1");
+        let err = router.call(&mut req).err().unwrap();
+        let err = err.downcast_ref::<RouterError>().unwrap();
+        assert_eq!(
+            err.allow_header().as_deref(),
+            Some("DELETE, GET, HEAD, OPTIONS")
+        );
+
+        let mut req = MockRequest::new(Method::POST, "/posts/new");
+        let err = router.call(&mut req).err().unwrap();
+        let err = err.downcast_ref::<RouterError>().unwrap();
+        assert_eq!(err.allow_header().as_deref(), Some("GET, HEAD, OPTIONS"));
+    }
+
+    fn test_router() -> RouteBuilder {
+        let mut router = RouteBuilder::new();
+        router.post("/posts/:id", test_handler);
+        router.get("/posts/:id", test_handler);
+        router
+    }
+
+    fn tag(tag: &'static str) -> impl Handler {
+        move |_: &mut dyn RequestExt| Response::builder().body(Body::from_static(tag.as_bytes()))
+    }
+
+    fn call(router: &RouteBuilder, path: &str) -> String {
+        call_with(router, &mut MockRequest::new(Method::GET, path))
+    }
+
+    fn call_with(router: &RouteBuilder, req: &mut MockRequest) -> String {
+        let res = router.call(req).expect("No response");
+        String::from_utf8(res.into_cow().into_owned()).unwrap()
+    }
+
+    fn mount_handler(req: &mut dyn conduit::RequestExt) -> conduit::HttpResult {
+        let res = [
+            req.virtual_root().unwrap_or("").to_string(),
+            req.path().to_string(),
+            req.extensions()
+                .get::<RoutePattern>()
+                .unwrap()
+                .pattern()
+                .to_string(),
+        ];
+        req.path_mut().push_str("/changed");
+
+        let bytes = res.join(", ").into_bytes();
+        Response::builder().body(Body::from_vec(bytes))
     }
 
     fn test_handler(req: &mut dyn conduit::RequestExt) -> conduit::HttpResult {
-        let res = vec![
+        let res = [
             req.params().find("id").unwrap_or("").to_string(),
             format!("{:?}", req.method()),
             req.extensions()
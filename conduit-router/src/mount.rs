@@ -0,0 +1,103 @@
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use conduit::{Extensions, HeaderMap, Host, Method, RequestExt, Scheme, Version};
+
+/// The prefixes of the mounts of handlers other than routers a request was
+/// dispatched through, joined.
+///
+/// Routers add it in front of the patterns they report in `RoutePattern`.
+#[derive(Clone, Debug)]
+pub(crate) struct MountPrefix(pub(crate) Arc<str>);
+
+/// A view of a request as seen by a handler mounted under a prefix.
+///
+/// The prefix moves from the path to the virtual root. Changes to the path
+/// made by the mounted handler don't affect the outer request.
+pub(crate) struct MountedRequest<'a> {
+    inner: &'a mut dyn RequestExt,
+    virtual_root: String,
+    path: String,
+}
+
+impl<'a> MountedRequest<'a> {
+    pub(crate) fn new(inner: &'a mut dyn RequestExt, prefix: &str) -> Self {
+        let virtual_root = format!("{}{}", inner.virtual_root().unwrap_or(""), prefix);
+        let path = match &inner.path()[prefix.len()..] {
+            "" => String::from("/"),
+            rest => rest.to_string(),
+        };
+        MountedRequest {
+            inner,
+            virtual_root,
+            path,
+        }
+    }
+}
+
+/// Whether `path` is below the mount `prefix`, which has no trailing slash.
+pub(crate) fn matches(prefix: &str, path: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+impl RequestExt for MountedRequest<'_> {
+    fn http_version(&self) -> Version {
+        self.inner.http_version()
+    }
+
+    fn method(&self) -> &Method {
+        self.inner.method()
+    }
+
+    fn scheme(&self) -> Scheme {
+        self.inner.scheme()
+    }
+
+    fn host(&self) -> Host<'_> {
+        self.inner.host()
+    }
+
+    fn virtual_root(&self) -> Option<&str> {
+        Some(&self.virtual_root)
+    }
+
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn path_mut(&mut self) -> &mut String {
+        &mut self.path
+    }
+
+    fn query_string(&self) -> Option<&str> {
+        self.inner.query_string()
+    }
+
+    fn remote_addr(&self) -> SocketAddr {
+        self.inner.remote_addr()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.inner.content_length()
+    }
+
+    fn headers(&self) -> &HeaderMap {
+        self.inner.headers()
+    }
+
+    fn body(&mut self) -> &mut dyn Read {
+        self.inner.body()
+    }
+
+    fn extensions(&self) -> &Extensions {
+        self.inner.extensions()
+    }
+
+    fn mut_extensions(&mut self) -> &mut Extensions {
+        self.inner.mut_extensions()
+    }
+}
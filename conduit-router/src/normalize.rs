@@ -0,0 +1,142 @@
+use conduit::{header, Body, HttpResult, RequestExt, Response, StatusCode};
+
+use crate::table::Table;
+
+/// How a router handles requests for a path that only matches a route once
+/// normalized, such as `/posts/1/` for a `/posts/:id` route.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathPolicy {
+    /// Don't normalize the path, so that the request isn't routed.
+    Strict,
+    /// Redirect to the normalized path with `301 Moved Permanently`.
+    MovedPermanently,
+    /// Redirect to the normalized path with `308 Permanent Redirect`, which
+    /// clients follow with the same method and body.
+    PermanentRedirect,
+    /// Handle the request as if it was for the normalized path.
+    Transparent,
+}
+
+impl Default for PathPolicy {
+    fn default() -> PathPolicy {
+        PathPolicy::Strict
+    }
+}
+
+impl PathPolicy {
+    pub(crate) fn redirect_status(self) -> Option<StatusCode> {
+        match self {
+            PathPolicy::MovedPermanently => Some(StatusCode::MOVED_PERMANENTLY),
+            PathPolicy::PermanentRedirect => Some(StatusCode::PERMANENT_REDIRECT),
+            PathPolicy::Strict | PathPolicy::Transparent => None,
+        }
+    }
+}
+
+/// The policies of a router for each kind of normalization.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Normalization {
+    pub(crate) trailing_slash: PathPolicy,
+    pub(crate) case: PathPolicy,
+    pub(crate) duplicate_slashes: PathPolicy,
+}
+
+impl Normalization {
+    /// The normalized path of a request matching no route, if it matches a
+    /// route, with the policy to apply.
+    ///
+    /// Duplicate slashes are merged, then a trailing slash is removed or
+    /// added, then the ASCII case of the static segments of patterns is
+    /// ignored. When several normalizations are needed, the request is
+    /// redirected if any of their policies redirects.
+    pub(crate) fn normalize(
+        &self,
+        table: &Table,
+        request: &dyn RequestExt,
+    ) -> Option<(String, PathPolicy)> {
+        let mut policies = Vec::new();
+        let path = match merge_slashes(request.path()) {
+            Some(merged) if self.duplicate_slashes != PathPolicy::Strict => {
+                policies.push(self.duplicate_slashes);
+                merged
+            }
+            _ => request.path().to_string(),
+        };
+
+        let mut candidates = vec![(path.clone(), None)];
+        if self.trailing_slash != PathPolicy::Strict && path != "/" {
+            let toggled = match path.strip_suffix('/') {
+                Some(path) => path.to_string(),
+                None => format!("{}/", path),
+            };
+            candidates.push((toggled, Some(self.trailing_slash)));
+        }
+
+        for (candidate, trailing_slash) in candidates {
+            let normalized = !policies.is_empty() || trailing_slash.is_some();
+            if normalized && table.contains(&candidate, request) {
+                policies.extend(trailing_slash);
+                return Some((candidate, redirecting(&policies)));
+            }
+            if self.case != PathPolicy::Strict {
+                let method = request.method();
+                if let Some(canonical) = table.canonical_path(method, &candidate, request) {
+                    policies.extend(trailing_slash);
+                    policies.push(self.case);
+                    return Some((canonical, redirecting(&policies)));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The first redirecting policy, or `Transparent` if none redirects.
+fn redirecting(policies: &[PathPolicy]) -> PathPolicy {
+    policies
+        .iter()
+        .copied()
+        .find(|policy| policy.redirect_status().is_some())
+        .unwrap_or(PathPolicy::Transparent)
+}
+
+/// The path with runs of slashes merged, if it has any.
+fn merge_slashes(path: &str) -> Option<String> {
+    if !path.contains("//") {
+        return None;
+    }
+    let mut merged = String::with_capacity(path.len());
+    for c in path.chars() {
+        if c != '/' || !merged.ends_with('/') {
+            merged.push(c);
+        }
+    }
+    Some(merged)
+}
+
+/// A redirect of the request to `path` with `status`, keeping its virtual
+/// root and query string.
+pub(crate) fn redirect(request: &dyn RequestExt, path: &str, status: StatusCode) -> HttpResult {
+    let root = request.virtual_root().unwrap_or("").trim_end_matches('/');
+    let mut location = format!("{}{}", root, path);
+    if let Some(query) = request.query_string() {
+        location.push('?');
+        location.push_str(query);
+    }
+    Response::builder()
+        .status(status)
+        .header(header::LOCATION, location)
+        .header(header::CONTENT_LENGTH, 0)
+        .body(Body::empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::merge_slashes;
+
+    #[test]
+    fn merge() {
+        assert_eq!(merge_slashes("/posts/1"), None);
+        assert_eq!(merge_slashes("//posts///1//").unwrap(), "/posts/1/");
+    }
+}
@@ -0,0 +1,296 @@
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use conduit::StatusCode;
+use percent_encoding::percent_decode_str;
+use route_recognizer::Params;
+use serde::de::value::{MapDeserializer, StrDeserializer};
+use serde::de::{self, Deserialize, IntoDeserializer, Visitor};
+
+/// An error extracting typed path parameters.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ParamError {
+    #[error("Missing path parameter `{0}`")]
+    Missing(String),
+    #[error("Invalid path parameter `{name}`: {message}")]
+    Invalid { name: String, message: String },
+    #[error("Invalid path parameters: {0}")]
+    Other(String),
+}
+
+impl ParamError {
+    /// The status code of the response a server should send for this error.
+    pub fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn invalid(name: &str, message: impl Display) -> ParamError {
+        ParamError::Invalid {
+            name: name.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl de::Error for ParamError {
+    fn custom<T: Display>(msg: T) -> Self {
+        ParamError::Other(msg.to_string())
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        ParamError::Missing(field.to_string())
+    }
+}
+
+pub(crate) fn parse<T>(params: &Params, name: &str) -> Result<T, ParamError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = params
+        .find(name)
+        .ok_or_else(|| ParamError::Missing(name.to_string()))?;
+    decode(name, value)?
+        .parse()
+        .map_err(|e| ParamError::invalid(name, e))
+}
+
+pub(crate) fn deserialize<'a, T: Deserialize<'a>>(params: &'a Params) -> Result<T, ParamError> {
+    let values = params
+        .iter()
+        .map(|(name, value)| (name, ValueDeserializer { name, value }));
+    T::deserialize(MapDeserializer::new(values))
+}
+
+/// Percent-decode the value of the parameter `name`.
+fn decode<'a>(name: &str, value: &'a str) -> Result<Cow<'a, str>, ParamError> {
+    percent_decode_str(value)
+        .decode_utf8()
+        .map_err(|e| ParamError::invalid(name, e))
+}
+
+/// Deserializes the value of a single parameter, parsing it as needed.
+struct ValueDeserializer<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+impl ValueDeserializer<'_> {
+    fn parse<T>(&self) -> Result<T, ParamError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        decode(self.name, self.value)?
+            .parse()
+            .map_err(|e| ParamError::invalid(self.name, e))
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ParamError> {
+                let value = self.parse()?;
+                visitor.$visit(value)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = ParamError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ParamError> {
+        match decode(self.name, self.value)? {
+            Cow::Borrowed(value) => visitor.visit_borrowed_str(value),
+            Cow::Owned(value) => visitor.visit_string(value),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ParamError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ParamError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, ParamError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ParamError> {
+        let value = decode(self.name, self.value)?;
+        let value: StrDeserializer<'_, ParamError> = value.as_ref().into_deserializer();
+        visitor.visit_enum(value).map_err(|e| match e {
+            ParamError::Other(message) => ParamError::invalid(self.name, message),
+            e => e,
+        })
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map
+        struct identifier
+    }
+}
+
+impl<'de> IntoDeserializer<'de, ParamError> for ValueDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{deserialize, parse, ParamError};
+
+    use route_recognizer::Params;
+    use serde::Deserialize;
+
+    fn params() -> Params {
+        let mut params = Params::new();
+        params.insert("id".into(), "10".into());
+        params.insert("slug".into(), "hello-world".into());
+        params.insert("kind".into(), "draft".into());
+        params
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum Kind {
+        Draft,
+        Published,
+    }
+
+    #[test]
+    fn parse_param() {
+        let params = params();
+        assert_eq!(parse::<u32>(&params, "id"), Ok(10));
+        assert_eq!(parse::<String>(&params, "slug").unwrap(), "hello-world");
+        assert_eq!(
+            parse::<u32>(&params, "missing"),
+            Err(ParamError::Missing("missing".into()))
+        );
+        assert_eq!(
+            parse::<u32>(&params, "slug").unwrap_err().to_string(),
+            "Invalid path parameter `slug`: invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn deserialize_struct() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Post<'a> {
+            id: u64,
+            slug: &'a str,
+            kind: Kind,
+            page: Option<u32>,
+        }
+
+        let params = params();
+        assert_eq!(
+            deserialize::<Post<'_>>(&params),
+            Ok(Post {
+                id: 10,
+                slug: "hello-world",
+                kind: Kind::Draft,
+                page: None,
+            })
+        );
+    }
+
+    #[test]
+    fn percent_decoded() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Post {
+            slug: String,
+            kind: Kind,
+        }
+
+        let mut params = Params::new();
+        params.insert("slug".into(), "hello%20world%2F%C3%A9".into());
+        params.insert("kind".into(), "dr%61ft".into());
+        params.insert("invalid".into(), "%FF".into());
+        assert_eq!(
+            parse::<String>(&params, "slug").unwrap(),
+            "hello world/\u{e9}"
+        );
+        assert_eq!(
+            deserialize::<Post>(&params),
+            Ok(Post {
+                slug: "hello world/\u{e9}".into(),
+                kind: Kind::Draft,
+            })
+        );
+        assert!(matches!(
+            parse::<String>(&params, "invalid"),
+            Err(ParamError::Invalid { name, .. }) if name == "invalid"
+        ));
+    }
+
+    #[test]
+    fn deserialize_errors() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Missing {
+            id: u64,
+            page: u32,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Invalid {
+            slug: i64,
+        }
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct InvalidEnum {
+            slug: Kind,
+        }
+
+        let params = params();
+        assert_eq!(
+            deserialize::<Missing>(&params).unwrap_err(),
+            ParamError::Missing("page".into())
+        );
+        assert!(matches!(
+            deserialize::<Invalid>(&params),
+            Err(ParamError::Invalid { name, .. }) if name == "slug"
+        ));
+        assert!(matches!(
+            deserialize::<InvalidEnum>(&params),
+            Err(ParamError::Invalid { name, .. }) if name == "slug"
+        ));
+    }
+}
@@ -0,0 +1,209 @@
+use std::sync::Arc;
+
+use conduit::header::{HeaderName, HeaderValue};
+use conduit::{Handler, Method, RequestExt};
+use conduit_middleware::{Middleware, MiddlewareBuilder};
+
+use crate::constraint::{Conditions, Constraint, Matcher};
+use crate::RouteBuilder;
+
+/// A route being registered with `RouteBuilder::route`.
+///
+/// The route is added once its handler is set with `Route::to`.
+#[must_use = "routes are only added to the router by `Route::to`"]
+pub struct Route<'a> {
+    builder: &'a mut RouteBuilder,
+    method: Method,
+    pattern: Arc<str>,
+    name: Option<String>,
+    conditions: Conditions,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl<'a> Route<'a> {
+    pub(crate) fn new(
+        builder: &'a mut RouteBuilder,
+        method: Method,
+        pattern: Arc<str>,
+        middleware: Vec<Arc<dyn Middleware>>,
+    ) -> Self {
+        Route {
+            builder,
+            method,
+            pattern,
+            name: None,
+            conditions: Conditions::default(),
+            middleware,
+        }
+    }
+
+    /// Name the route, so that its URL can be generated with
+    /// `Urls::url_for`.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Only match requests where the parameter `param` satisfies
+    /// `constraint`.
+    ///
+    /// # Panics
+    ///
+    /// When the route is added, if its pattern has no parameter `param`.
+    pub fn constraint(mut self, param: &str, constraint: Constraint) -> Self {
+        self.conditions
+            .constraints
+            .push((param.to_string(), constraint));
+        self
+    }
+
+    /// Only match requests for `host`, ignoring case and the port.
+    pub fn host(mut self, host: &str) -> Self {
+        self.conditions
+            .matchers
+            .push(Matcher::Host(host.to_string()));
+        self
+    }
+
+    /// Only match requests with a `name` header equal to `value`.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.conditions.matchers.push(Matcher::Header(name, value));
+        self
+    }
+
+    /// Only match requests accepting `media_type`, such as
+    /// `application/json`, according to their `Accept` header.
+    ///
+    /// Requests without an `Accept` header accept any type.
+    pub fn accept(mut self, media_type: &str) -> Self {
+        self.conditions
+            .matchers
+            .push(Matcher::Accept(media_type.to_string()));
+        self
+    }
+
+    /// Only match requests for which `predicate` returns `true`.
+    pub fn matcher<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&dyn RequestExt) -> bool + Send + Sync + 'static,
+    {
+        self.conditions
+            .matchers
+            .push(Matcher::Custom(Arc::new(predicate)));
+        self
+    }
+
+    /// Run `middleware` around the handler of this route only.
+    ///
+    /// Middleware runs in the order it was added, after that of the groups
+    /// the route belongs to, with the semantics of `MiddlewareBuilder`.
+    pub fn middleware<M: Middleware>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Handle requests matching the route with `handler`.
+    ///
+    /// # Panics
+    ///
+    /// If a route with the same constraints and matchers, or without any, is
+    /// already registered for the method and a pattern matching the same
+    /// paths, such as `/posts/:id` and `/posts/:slug`.
+    pub fn to<H: Handler>(self, handler: H) -> &'a mut RouteBuilder {
+        let handler: Box<dyn Handler> = if self.middleware.is_empty() {
+            Box::new(handler)
+        } else {
+            let mut stack = MiddlewareBuilder::new(handler);
+            for middleware in self.middleware {
+                stack.add(middleware);
+            }
+            Box::new(stack)
+        };
+        self.builder.add(
+            self.method,
+            self.pattern,
+            self.name,
+            self.conditions,
+            handler,
+        );
+        self.builder
+    }
+}
+
+/// Routes sharing a path prefix and middleware, created with
+/// `RouteBuilder::group`.
+pub struct Group<'a> {
+    builder: &'a mut RouteBuilder,
+    prefix: String,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl<'a> Group<'a> {
+    pub(crate) fn new(builder: &'a mut RouteBuilder, prefix: &str) -> Self {
+        Group {
+            builder,
+            prefix: prefix.trim_end_matches('/').to_string(),
+            middleware: Vec::new(),
+        }
+    }
+
+    /// Run `middleware` around the handlers of the routes added to the group
+    /// afterwards.
+    pub fn middleware<M: Middleware>(&mut self, middleware: M) -> &mut Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Create a nested group, with the prefix and middleware of this group.
+    pub fn group(&mut self, prefix: &str) -> Group<'_> {
+        Group {
+            builder: self.builder,
+            prefix: format!("{}{}", self.prefix, prefix.trim_end_matches('/')),
+            middleware: self.middleware.clone(),
+        }
+    }
+
+    /// Start registering a route, with `pattern` relative to the prefix of
+    /// the group.
+    pub fn route(&mut self, method: Method, pattern: &str) -> Route<'_> {
+        let pattern = match pattern {
+            "" | "/" if !self.prefix.is_empty() => self.prefix.clone(),
+            pattern => format!("{}{}", self.prefix, pattern),
+        };
+        Route::new(
+            self.builder,
+            method,
+            pattern.into(),
+            self.middleware.clone(),
+        )
+    }
+
+    pub fn map<H: Handler>(&mut self, method: Method, pattern: &str, handler: H) -> &mut Self {
+        self.route(method, pattern).to(handler);
+        self
+    }
+
+    pub fn get<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Self {
+        self.map(Method::GET, pattern, handler)
+    }
+
+    pub fn post<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Self {
+        self.map(Method::POST, pattern, handler)
+    }
+
+    pub fn put<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Self {
+        self.map(Method::PUT, pattern, handler)
+    }
+
+    pub fn delete<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Self {
+        self.map(Method::DELETE, pattern, handler)
+    }
+
+    pub fn head<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Self {
+        self.map(Method::HEAD, pattern, handler)
+    }
+
+    pub fn options<H: Handler>(&mut self, pattern: &str, handler: H) -> &mut Self {
+        self.map(Method::OPTIONS, pattern, handler)
+    }
+}
@@ -0,0 +1,464 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+
+use conduit::{Method, RequestExt};
+use route_recognizer::{Match, Params, Router};
+
+use crate::constraint::Conditions;
+use crate::WrappedHandler;
+
+/// How specific a pattern is, compared like `route_recognizer` does: by the
+/// number of static, then dynamic, then glob segments.
+type Specificity = (u32, u32, u32);
+
+pub(crate) struct RouteEntry {
+    /// The pattern with parameters named by their position, such as
+    /// `posts/:0`, equal for patterns matching the same paths.
+    shape: String,
+    /// The pattern with its leading `/` removed, such as `posts/:id`.
+    named: String,
+    param_names: Vec<String>,
+    specificity: Specificity,
+    conditions: Conditions,
+    name: Option<String>,
+    pub(crate) handler: WrappedHandler,
+}
+
+impl RouteEntry {
+    pub(crate) fn new(
+        pattern: &str,
+        name: Option<String>,
+        conditions: Conditions,
+        handler: WrappedHandler,
+    ) -> Self {
+        let named = pattern.strip_prefix('/').unwrap_or(pattern);
+        let mut positional = String::with_capacity(pattern.len());
+        let mut param_names = Vec::new();
+        let mut specificity = (0, 0, 0);
+        for (separator, segment) in segments(named) {
+            positional.extend(separator);
+            if let Some(name) = segment.strip_prefix(':') {
+                positional.push_str(&format!(":{}", param_names.len()));
+                param_names.push(name.to_string());
+                specificity.1 += 1;
+            } else if let Some(name) = segment.strip_prefix('*') {
+                positional.push_str(&format!("*{}", param_names.len()));
+                param_names.push(name.to_string());
+                specificity.2 += 1;
+            } else {
+                positional.push_str(segment);
+                specificity.0 += 1;
+            }
+        }
+
+        for (name, _) in &conditions.constraints {
+            assert!(
+                param_names.contains(name),
+                "route {:?} has no parameter {:?} to constrain",
+                pattern,
+                name
+            );
+        }
+
+        RouteEntry {
+            shape: positional,
+            named: named.to_string(),
+            param_names,
+            specificity,
+            conditions,
+            name,
+            handler,
+        }
+    }
+
+    fn pattern(&self) -> &str {
+        self.handler.pattern.pattern()
+    }
+
+    /// The parameters of this route, from those recognized for the pattern
+    /// of another route of the same shape with the parameters `names`.
+    fn rename(&self, params: &Params, names: &[String]) -> Params {
+        let mut renamed = Params::new();
+        for (name, other) in self.param_names.iter().zip(names) {
+            if let (false, Some(value)) = (name.is_empty(), params.find(other)) {
+                renamed.insert(name.clone(), value.to_string());
+            }
+        }
+        renamed
+    }
+}
+
+/// Split a pattern like `route_recognizer` does, into segments with their
+/// preceding `/` or `.` separator.
+pub(crate) fn segments(pattern: &str) -> Vec<(Option<char>, &str)> {
+    let is_separator = |c| c == '.' || c == '/';
+
+    let mut segments = Vec::new();
+    let mut start = 0;
+    while start < pattern.len() {
+        let end = pattern[start + 1..]
+            .find(is_separator)
+            .map_or(pattern.len(), |i| i + start + 1);
+        match pattern[start..].chars().next() {
+            Some(c) if is_separator(c) => segments.push((Some(c), &pattern[start + 1..end])),
+            _ => segments.push((None, &pattern[start..end])),
+        }
+        start = end;
+    }
+    segments
+}
+
+/// The routes of a method whose patterns match the same paths, such as
+/// `/posts/:id` and `/posts/:slug`.
+///
+/// Routes are kept in the order they are tried: routes with more constraints
+/// and matchers first, then in the order they were registered.
+struct Shape {
+    /// The parameter names of the first route, which its pattern is
+    /// recognized with.
+    param_names: Vec<String>,
+    /// Recognizes the pattern of the first route.
+    recognizer: Router<()>,
+    /// Recognizes the shape in ASCII lowercase.
+    folded: Router<()>,
+    routes: Vec<RouteEntry>,
+}
+
+impl Shape {
+    fn new(entry: RouteEntry) -> Shape {
+        let mut recognizer = Router::new();
+        recognizer.add(&entry.named, ());
+        let mut folded = Router::new();
+        folded.add(&entry.shape.to_ascii_lowercase(), ());
+        Shape {
+            param_names: entry.param_names.clone(),
+            recognizer,
+            folded,
+            routes: vec![entry],
+        }
+    }
+
+    fn shape(&self) -> &str {
+        &self.routes[0].shape
+    }
+
+    fn specificity(&self) -> Specificity {
+        self.routes[0].specificity
+    }
+
+    fn add(&mut self, entry: RouteEntry) {
+        let conditions = entry.conditions.len();
+        let index = self
+            .routes
+            .partition_point(|route| route.conditions.len() >= conditions);
+        self.routes.insert(index, entry);
+    }
+
+    /// The first route matching the request, given the parameters recognized
+    /// for the pattern of the first route.
+    fn find(&self, params: Params, request: &dyn RequestExt) -> Option<(&RouteEntry, Params)> {
+        for route in &self.routes {
+            if route.param_names == self.param_names {
+                if route.conditions.matches(&params, request) {
+                    return Some((route, params));
+                }
+            } else {
+                let params = route.rename(&params, &self.param_names);
+                if route.conditions.matches(&params, request) {
+                    return Some((route, params));
+                }
+            }
+        }
+        None
+    }
+
+    /// The path of the first route that `path` is equal to, ignoring the
+    /// ASCII case of the static segments of the pattern.
+    fn canonical_path(&self, path: &str, request: &dyn RequestExt) -> Option<String> {
+        let m = self.folded.recognize(&path.to_ascii_lowercase()).ok()?;
+
+        // Lowercasing ASCII doesn't move bytes, so the values of the
+        // parameters are found at the same offsets in `path`.
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let mut canonical = String::with_capacity(path.len() + 1);
+        let mut params = Params::new();
+        let mut offset = 0;
+        for (separator, segment) in segments(self.shape()) {
+            if let Some(separator) = separator {
+                canonical.push(separator);
+                offset += 1;
+            }
+            let text = match segment.strip_prefix(|c| c == ':' || c == '*') {
+                Some(i) => {
+                    let len = m.params().find(i)?.len();
+                    let value = path.get(offset..offset + len)?;
+                    params.insert(i.to_string(), value.to_string());
+                    value
+                }
+                None => segment,
+            };
+            canonical.push_str(text);
+            offset += text.len();
+        }
+
+        let positions: Vec<_> = (0..self.param_names.len()).map(|i| i.to_string()).collect();
+        let matches = self.routes.iter().any(|route| {
+            let params = route.rename(&params, &positions);
+            route.conditions.matches(&params, request)
+        });
+        if matches {
+            Some(format!("/{}", canonical))
+        } else {
+            None
+        }
+    }
+}
+
+fn into_params<T>(mut m: Match<T>) -> Params {
+    // We don't have `pub` access to the fields to destructure `Params`, so swap with an empty
+    // value to avoid an allocation.
+    let mut params = Params::new();
+    std::mem::swap(m.params_mut(), &mut params);
+    params
+}
+
+/// The routes of a method.
+#[derive(Default)]
+struct Routes {
+    /// Recognizes the patterns of all shapes, mapping them to their index in
+    /// `shapes`.
+    recognizer: Router<usize>,
+    /// The shapes in the order they were registered.
+    shapes: Vec<Shape>,
+    /// The indices of `shapes`, more specific patterns first.
+    by_specificity: Vec<usize>,
+}
+
+impl Routes {
+    fn find(&self, path: &str, request: &dyn RequestExt) -> Option<(&RouteEntry, Params)> {
+        let m = self.recognizer.recognize(path).ok()?;
+        let best = **m.handler();
+        if let Some(found) = self.shapes[best].find(into_params(m), request) {
+            return Some(found);
+        }
+
+        // The constraints or matchers of every route of the most specific
+        // shape failed, so fall through to the less specific ones.
+        self.by_specificity
+            .iter()
+            .filter(|&&i| i != best)
+            .map(|&i| &self.shapes[i])
+            .find_map(|shape| {
+                let m = shape.recognizer.recognize(path).ok()?;
+                shape.find(into_params(m), request)
+            })
+    }
+
+    fn shapes(&self) -> impl Iterator<Item = &Shape> {
+        self.by_specificity.iter().map(move |&i| &self.shapes[i])
+    }
+}
+
+/// The routes of a `RouteBuilder`, by method.
+///
+/// Routes whose patterns match the same paths are grouped into a shape. A
+/// request is matched against the most specific shape first, then less
+/// specific ones, in the order of `route_recognizer`. The first route of the
+/// shape whose constraints and matchers all match the request handles it.
+#[derive(Default)]
+pub(crate) struct Table {
+    routes: HashMap<Method, Routes>,
+}
+
+impl Table {
+    /// Add a route.
+    ///
+    /// # Panics
+    ///
+    /// If a route with the same constraints and matchers is already
+    /// registered for the method and a pattern matching the same paths, as
+    /// the new route could never be used. Custom constraints and matchers
+    /// are never considered the same.
+    pub(crate) fn add(&mut self, method: Method, entry: RouteEntry) {
+        let routes = self.routes.entry(method.clone()).or_default();
+        let index = routes
+            .shapes
+            .iter()
+            .position(|shape| shape.shape() == entry.shape);
+        let index = match index {
+            Some(index) => index,
+            None => {
+                let specificity = Reverse(entry.specificity);
+                let position = routes
+                    .by_specificity
+                    .partition_point(|&i| Reverse(routes.shapes[i].specificity()) <= specificity);
+                routes.recognizer.add(&entry.named, routes.shapes.len());
+                routes.by_specificity.insert(position, routes.shapes.len());
+                routes.shapes.push(Shape::new(entry));
+                return;
+            }
+        };
+
+        let shape = &mut routes.shapes[index];
+        let shadowing = shape.routes.iter().find(|route| {
+            route
+                .conditions
+                .same_as(&route.param_names, &entry.conditions, &entry.param_names)
+        });
+        if let Some(route) = shadowing {
+            if route.pattern() == entry.pattern() {
+                panic!("route {} {} is already registered", method, entry.pattern());
+            }
+            panic!(
+                "route {} {} is shadowed by {} {}",
+                method,
+                entry.pattern(),
+                method,
+                route.pattern()
+            );
+        }
+        shape.add(entry);
+    }
+
+    pub(crate) fn handlers_mut(&mut self) -> impl Iterator<Item = &mut WrappedHandler> {
+        self.routes
+            .values_mut()
+            .flat_map(|routes| &mut routes.shapes)
+            .flat_map(|shape| &mut shape.routes)
+            .map(|route| &mut route.handler)
+    }
+
+    pub(crate) fn contains_method(&self, method: &Method) -> bool {
+        self.routes.contains_key(method)
+    }
+
+    pub(crate) fn methods(&self) -> impl Iterator<Item = &Method> {
+        self.routes.keys()
+    }
+
+    /// The registered routes, sorted by pattern and method.
+    pub(crate) fn routes(&self) -> Vec<RouteInfo<'_>> {
+        let mut routes: Vec<_> = self
+            .routes
+            .iter()
+            .flat_map(|(method, routes)| {
+                routes
+                    .shapes
+                    .iter()
+                    .flat_map(|shape| &shape.routes)
+                    .map(move |route| RouteInfo {
+                        method,
+                        pattern: route.pattern(),
+                        name: route.name.as_deref(),
+                    })
+            })
+            .collect();
+        routes.sort_by(|a, b| (a.pattern, a.method.as_str()).cmp(&(b.pattern, b.method.as_str())));
+        routes
+    }
+
+    /// The first route for `method` matching the request for `path`.
+    pub(crate) fn find(
+        &self,
+        method: &Method,
+        path: &str,
+        request: &dyn RequestExt,
+    ) -> Option<(&RouteEntry, Params)> {
+        self.routes.get(method)?.find(path, request)
+    }
+
+    /// Whether a route of any method matches the request for `path`.
+    pub(crate) fn contains(&self, path: &str, request: &dyn RequestExt) -> bool {
+        self.methods()
+            .any(|method| self.find(method, path, request).is_some())
+    }
+
+    /// The path of the first route matching the request for `path` when
+    /// ignoring ASCII case, trying the routes of `method` first.
+    pub(crate) fn canonical_path(
+        &self,
+        method: &Method,
+        path: &str,
+        request: &dyn RequestExt,
+    ) -> Option<String> {
+        let others = self.routes.iter().filter(|(m, _)| *m != method);
+        self.routes
+            .get(method)
+            .into_iter()
+            .chain(others.map(|(_, routes)| routes))
+            .flat_map(Routes::shapes)
+            .find_map(|shape| shape.canonical_path(path, request))
+    }
+}
+
+/// A route registered with a `RouteBuilder`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RouteInfo<'a> {
+    method: &'a Method,
+    pattern: &'a str,
+    name: Option<&'a str>,
+}
+
+impl<'a> RouteInfo<'a> {
+    pub fn method(&self) -> &'a Method {
+        self.method
+    }
+
+    pub fn pattern(&self) -> &'a str {
+        self.pattern
+    }
+
+    pub fn name(&self) -> Option<&'a str> {
+        self.name
+    }
+}
+
+/// The routes of a `RouteBuilder`, displayed as a table with a line per
+/// route, such as `GET  /posts/:id  post`.
+#[derive(Debug)]
+pub struct RouteTable<'a>(pub(crate) Vec<RouteInfo<'a>>);
+
+impl fmt::Display for RouteTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let method_width = self.0.iter().map(|r| r.method.as_str().len()).max();
+        let pattern_width = self.0.iter().map(|r| r.pattern.len()).max();
+        let (method_width, pattern_width) = (method_width.unwrap_or(0), pattern_width.unwrap_or(0));
+        for route in &self.0 {
+            match route.name {
+                Some(name) => writeln!(
+                    f,
+                    "{:<mw$}  {:<pw$}  {}",
+                    route.method.as_str(),
+                    route.pattern,
+                    name,
+                    mw = method_width,
+                    pw = pattern_width,
+                )?,
+                None => writeln!(
+                    f,
+                    "{:<mw$}  {}",
+                    route.method.as_str(),
+                    route.pattern,
+                    mw = method_width,
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::segments;
+
+    #[test]
+    fn split_segments() {
+        assert_eq!(
+            segments("posts/:id.json"),
+            [(None, "posts"), (Some('/'), ":id"), (Some('.'), "json")]
+        );
+        assert_eq!(segments("*path"), [(None, "*path")]);
+        assert_eq!(segments(""), []);
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+use crate::table::segments;
+
+/// Characters that must be escaped in a path segment.
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'\\')
+    .add(b'^')
+    .add(b'`')
+    .add(b'{')
+    .add(b'|')
+    .add(b'}');
+
+/// An error generating the URL of a named route.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum UrlError {
+    #[error("No route named `{0}`")]
+    UnknownRoute(String),
+    #[error("Missing parameter `{param}` for route `{route}`")]
+    MissingParam { route: String, param: String },
+}
+
+/// Generates URLs of named routes.
+///
+/// Routers insert it into the request extensions, so that handlers can link
+/// to other routes of the router that dispatched them:
+///
+/// ```
+/// # use conduit::RequestExt;
+/// # use conduit_router::Urls;
+/// # fn link(req: &dyn RequestExt) {
+/// let urls = req.extensions().get::<Urls>().unwrap();
+/// let url = urls.url_for("post", &[("id", "1")]);
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Urls {
+    patterns: Arc<HashMap<String, String>>,
+    root: String,
+}
+
+impl Urls {
+    pub(crate) fn new(patterns: Arc<HashMap<String, String>>, root: &str) -> Urls {
+        Urls {
+            patterns,
+            root: root.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// The path of the route named `name`, with its parameters filled in
+    /// from `params`.
+    ///
+    /// Values are percent-encoded, except for the slashes of glob parameters
+    /// such as `*path`. Use `*` as the name of an unnamed glob. The path
+    /// starts with the virtual root of the request, so that it's also
+    /// correct for routers mounted under a prefix. Parameters not used by
+    /// the pattern are ignored.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, UrlError> {
+        let pattern = self
+            .patterns
+            .get(name)
+            .ok_or_else(|| UrlError::UnknownRoute(name.to_string()))?;
+        let find = |param: &str| {
+            params
+                .iter()
+                .find(|(name, _)| *name == param)
+                .map(|(_, value)| *value)
+                .ok_or_else(|| UrlError::MissingParam {
+                    route: name.to_string(),
+                    param: param.to_string(),
+                })
+        };
+
+        let mut url = self.root.clone();
+        for (separator, segment) in segments(pattern) {
+            url.extend(separator);
+            if let Some(param) = segment.strip_prefix(':') {
+                url.extend(utf8_percent_encode(find(param)?, SEGMENT));
+            } else if let Some(param) = segment.strip_prefix('*') {
+                let value = find(if param.is_empty() { "*" } else { param })?;
+                let value = value.strip_prefix('/').unwrap_or(value);
+                let segments: Vec<String> = value
+                    .split('/')
+                    .map(|segment| utf8_percent_encode(segment, SEGMENT).to_string())
+                    .collect();
+                url.push_str(&segments.join("/"));
+            } else {
+                url.push_str(segment);
+            }
+        }
+        if url.is_empty() {
+            url.push('/');
+        }
+        Ok(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use super::{UrlError, Urls};
+
+    fn urls(root: &str) -> Urls {
+        let patterns = [
+            ("root", "/"),
+            ("post", "/posts/:id"),
+            ("post_json", "/posts/:id.json"),
+            ("comment", "/posts/:post_id/comments/:id"),
+            ("file", "/files/*path"),
+            ("any", "/any/*"),
+        ];
+        let patterns: HashMap<_, _> = patterns
+            .iter()
+            .map(|(name, pattern)| (name.to_string(), pattern.to_string()))
+            .collect();
+        Urls::new(Arc::new(patterns), root)
+    }
+
+    #[test]
+    fn url_for() {
+        let urls = urls("");
+        assert_eq!(urls.url_for("root", &[]).unwrap(), "/");
+        assert_eq!(urls.url_for("post", &[("id", "1")]).unwrap(), "/posts/1");
+        assert_eq!(
+            urls.url_for("post_json", &[("id", "1")]).unwrap(),
+            "/posts/1.json"
+        );
+        assert_eq!(
+            urls.url_for("comment", &[("id", "2"), ("post_id", "1")])
+                .unwrap(),
+            "/posts/1/comments/2"
+        );
+    }
+
+    #[test]
+    fn percent_encoding() {
+        let urls = urls("");
+        assert_eq!(
+            urls.url_for("post", &[("id", "a b/c?d#e%f")]).unwrap(),
+            "/posts/a%20b%2Fc%3Fd%23e%25f"
+        );
+        assert_eq!(
+            urls.url_for("post", &[("id", "🎉")]).unwrap(),
+            "/posts/%F0%9F%8E%89"
+        );
+        assert_eq!(
+            urls.url_for("file", &[("path", "docs/a b.txt")]).unwrap(),
+            "/files/docs/a%20b.txt"
+        );
+        assert_eq!(urls.url_for("any", &[("*", "/x/y")]).unwrap(), "/any/x/y");
+    }
+
+    #[test]
+    fn virtual_root() {
+        let urls = urls("/api/");
+        assert_eq!(urls.url_for("root", &[]).unwrap(), "/api/");
+        assert_eq!(
+            urls.url_for("post", &[("id", "1")]).unwrap(),
+            "/api/posts/1"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let urls = urls("");
+        assert_eq!(
+            urls.url_for("nope", &[]),
+            Err(UrlError::UnknownRoute("nope".into()))
+        );
+        assert_eq!(
+            urls.url_for("comment", &[("id", "2")]),
+            Err(UrlError::MissingParam {
+                route: "comment".into(),
+                param: "post_id".into()
+            })
+        );
+    }
+}
@@ -0,0 +1,489 @@
+#![warn(rust_2018_idioms)]
+
+//! A small, blocking HTTP/1.1 server for running any `conduit::Handler`.
+//!
+//! Connections are accepted on a background thread and served by a fixed
+//! pool of worker threads. Persistent connections and chunked transfer
+//! coding are supported for both requests and responses.
+//!
+//! ```no_run
+//! use conduit::{Body, HttpResult, RequestExt, Response};
+//! use conduit_server::{Config, Server};
+//!
+//! fn hello(_req: &mut dyn RequestExt) -> HttpResult {
+//!     Response::builder().body(Body::from_static(b"Hello world!"))
+//! }
+//!
+//! let mut cfg = Config::new();
+//! cfg.port(8888).threads(4);
+//! let _server = Server::start(cfg, hello).unwrap();
+//! ```
+
+#[macro_use]
+extern crate tracing;
+
+mod request;
+mod response;
+
+use std::io::{self, BufReader, BufWriter};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use conduit::{header, Body, Handler, RequestExt, Response, StatusCode, Version};
+
+use crate::request::{write_continue, ParseError, ServerRequest};
+use crate::response::write_response;
+
+/// Configuration for a `Server`.
+#[derive(Clone, Debug)]
+pub struct Config {
+    ip: IpAddr,
+    port: u16,
+    threads: u32,
+    keep_alive_timeout: Duration,
+}
+
+impl Config {
+    /// Listen on `0.0.0.0:8888` with 50 worker threads.
+    pub fn new() -> Config {
+        Config {
+            ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            port: 8888,
+            threads: 50,
+            keep_alive_timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// The IP address to listen on.
+    pub fn ip(&mut self, ip: IpAddr) -> &mut Config {
+        self.ip = ip;
+        self
+    }
+
+    /// The port to listen on. Port `0` asks the OS for a free port, which can
+    /// be found with `Server::local_addr`.
+    pub fn port(&mut self, port: u16) -> &mut Config {
+        self.port = port;
+        self
+    }
+
+    /// The number of worker threads, which is also the maximum number of
+    /// connections served concurrently.
+    pub fn threads(&mut self, threads: u32) -> &mut Config {
+        self.threads = threads;
+        self
+    }
+
+    /// How long a connection may stay idle, or block while reading a
+    /// request, before it is closed.
+    pub fn keep_alive_timeout(&mut self, timeout: Duration) -> &mut Config {
+        self.keep_alive_timeout = timeout;
+        self
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A running server.
+///
+/// The server stops accepting connections when this value is dropped.
+/// Connections that are already being served are finished by their worker.
+pub struct Server {
+    local_addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+    acceptor: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    /// Bind to the configured address and start serving `handler`.
+    pub fn start<H: Handler>(config: Config, handler: H) -> io::Result<Server> {
+        let listener = TcpListener::bind((config.ip, config.port))?;
+        let local_addr = listener.local_addr()?;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let handler = Arc::new(handler);
+
+        let (tx, rx) = channel::<TcpStream>();
+        let rx = Arc::new(Mutex::new(rx));
+        for i in 0..config.threads.max(1) {
+            let rx = rx.clone();
+            let handler = handler.clone();
+            let timeout = config.keep_alive_timeout;
+            thread::Builder::new()
+                .name(format!("conduit-server-worker-{}", i))
+                .spawn(move || worker(&rx, &*handler, timeout))?;
+        }
+
+        let acceptor = {
+            let shutdown = shutdown.clone();
+            thread::Builder::new()
+                .name("conduit-server-acceptor".into())
+                .spawn(move || {
+                    for stream in listener.incoming() {
+                        if shutdown.load(Ordering::SeqCst) {
+                            break;
+                        }
+                        match stream {
+                            Ok(stream) => {
+                                if tx.send(stream).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(e) => warn!("failed to accept connection: {}", e),
+                        }
+                    }
+                })?
+        };
+
+        Ok(Server {
+            local_addr,
+            shutdown,
+            acceptor: Some(acceptor),
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake up the acceptor, which is blocked in `accept`
+        let addr = match self.local_addr.ip() {
+            ip if ip.is_unspecified() && ip.is_ipv4() => {
+                SocketAddr::new(Ipv4Addr::LOCALHOST.into(), self.local_addr.port())
+            }
+            ip if ip.is_unspecified() => {
+                SocketAddr::new(std::net::Ipv6Addr::LOCALHOST.into(), self.local_addr.port())
+            }
+            _ => self.local_addr,
+        };
+        let _ = TcpStream::connect(addr);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+    }
+}
+
+fn worker(rx: &Mutex<Receiver<TcpStream>>, handler: &dyn Handler, timeout: Duration) {
+    loop {
+        // Release the lock before serving the connection
+        let stream = match rx.lock().unwrap_or_else(PoisonError::into_inner).recv() {
+            Ok(stream) => stream,
+            Err(_) => return,
+        };
+        // A panic only closes its connection, so that the thread keeps
+        // serving the next ones
+        match panic::catch_unwind(AssertUnwindSafe(|| {
+            serve_connection(stream, handler, timeout)
+        })) {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => debug!("connection closed with error: {}", e),
+            Err(_) => error!("connection closed after a panic"),
+        }
+    }
+}
+
+fn serve_connection(stream: TcpStream, handler: &dyn Handler, timeout: Duration) -> io::Result<()> {
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_nodelay(true)?;
+    let local_addr = stream.local_addr()?;
+    let remote_addr = stream.peer_addr()?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+
+    loop {
+        let mut request = match ServerRequest::read(&mut reader, local_addr, remote_addr) {
+            Ok(request) => request,
+            Err(ParseError::Closed) => return Ok(()),
+            Err(ParseError::Io(e)) => return Err(e),
+            Err(ParseError::Invalid) => {
+                let method = conduit::Method::GET;
+                let response = error_response(StatusCode::BAD_REQUEST);
+                write_response(&mut writer, &method, Version::HTTP_11, false, response)?;
+                return Ok(());
+            }
+        };
+
+        if request.expects_continue() {
+            write_continue(&mut writer)?;
+        }
+
+        let method = request.method().clone();
+        let version = request.http_version();
+        let mut keep_alive = request.wants_keep_alive();
+
+        let response = match panic::catch_unwind(AssertUnwindSafe(|| handler.call(&mut request))) {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => {
+                error!(method = %method, path = request.path(), "handler error: {}", e);
+                error_response(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+            Err(_) => {
+                error!(method = %method, path = request.path(), "handler panicked");
+                // The request body may be half read
+                keep_alive = false;
+                error_response(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        };
+
+        // Any unread request body must be consumed before the next request
+        let keep_alive = keep_alive && request.drain_body().is_ok();
+        drop(request);
+
+        if !write_response(&mut writer, &method, version, keep_alive, response)? {
+            return Ok(());
+        }
+    }
+}
+
+fn error_response(status: StatusCode) -> Response<Body> {
+    let reason = status.canonical_reason().unwrap_or("").as_bytes();
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from_static(reason))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, Server};
+
+    use std::io::{self, Read, Write};
+    use std::net::{Ipv4Addr, TcpStream};
+
+    use conduit::{header, Body, HttpResult, RequestExt, Response, StartInstant};
+
+    fn start<H: conduit::Handler>(handler: H) -> Server {
+        let mut cfg = Config::new();
+        cfg.ip(Ipv4Addr::LOCALHOST.into()).port(0).threads(2);
+        Server::start(cfg, handler).unwrap()
+    }
+
+    fn send(server: &Server, request: &str) -> String {
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn echo(req: &mut dyn RequestExt) -> HttpResult {
+        assert!(req.extensions().get::<StartInstant>().is_some());
+        let mut body = Vec::new();
+        req.body().read_to_end(&mut body).unwrap();
+        let text = format!(
+            "{} {} {:?} {}",
+            req.method(),
+            req.path(),
+            req.query_string(),
+            String::from_utf8(body).unwrap()
+        );
+        Response::builder().body(Body::from_vec(text.into_bytes()))
+    }
+
+    fn stream(_: &mut dyn RequestExt) -> HttpResult {
+        Response::builder().body(Body::from_reader(&b"streamed body"[..]))
+    }
+
+    fn failing(_: &mut dyn RequestExt) -> io::Result<Response<Body>> {
+        Err(io::Error::new(io::ErrorKind::Other, "boom"))
+    }
+
+    fn panicking(_: &mut dyn RequestExt) -> HttpResult {
+        panic!("boom")
+    }
+
+    #[test]
+    fn simple_get() {
+        let server = start(echo);
+        let res = send(
+            &server,
+            "GET /foo?bar=baz HTTP/1.1\r\nConnection: close\r\n\r\n",
+        );
+
+        assert!(res.starts_with("HTTP/1.1 200 OK\r\n"), "{}", res);
+        assert!(res.contains("\r\ncontent-length: 25\r\n"), "{}", res);
+        assert!(res.contains("\r\nconnection: close\r\n"), "{}", res);
+        assert!(res.contains("\r\ndate: "), "{}", res);
+        assert!(
+            res.ends_with("\r\n\r\nGET /foo Some(\"bar=baz\") "),
+            "{}",
+            res
+        );
+    }
+
+    #[test]
+    fn request_body_with_length() {
+        let server = start(echo);
+        let res = send(
+            &server,
+            "POST / HTTP/1.1\r\nContent-Length: 5\r\nConnection: close\r\n\r\nhello",
+        );
+
+        assert!(res.ends_with("POST / None hello"), "{}", res);
+    }
+
+    #[test]
+    fn chunked_request_body() {
+        let server = start(echo);
+        let res = send(
+            &server,
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n\
+             5\r\nhello\r\n6;ext=1\r\n world\r\n0\r\n\r\n",
+        );
+
+        assert!(res.ends_with("POST / None hello world"), "{}", res);
+    }
+
+    #[test]
+    fn keep_alive() {
+        let server = start(echo);
+        let res = send(
+            &server,
+            "POST /a HTTP/1.1\r\nContent-Length: 3\r\n\r\none\
+             GET /b HTTP/1.1\r\nConnection: close\r\n\r\n",
+        );
+
+        assert_eq!(res.matches("HTTP/1.1 200 OK").count(), 2, "{}", res);
+        assert!(res.contains("POST /a None one"), "{}", res);
+        assert!(res.ends_with("GET /b None "), "{}", res);
+    }
+
+    #[test]
+    fn unread_body_is_drained() {
+        let server = start(|_: &mut dyn RequestExt| Response::builder().body(Body::empty()));
+        let res = send(
+            &server,
+            "POST /a HTTP/1.1\r\nContent-Length: 3\r\n\r\none\
+             GET /b HTTP/1.1\r\nConnection: close\r\n\r\n",
+        );
+
+        assert_eq!(res.matches("HTTP/1.1 200 OK").count(), 2, "{}", res);
+    }
+
+    #[test]
+    fn chunked_response() {
+        let server = start(stream);
+        let res = send(&server, "GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+
+        assert!(
+            res.contains("\r\ntransfer-encoding: chunked\r\n"),
+            "{}",
+            res
+        );
+        assert!(!res.contains("content-length"), "{}", res);
+        assert!(
+            res.ends_with("\r\n\r\nD\r\nstreamed body\r\n0\r\n\r\n"),
+            "{}",
+            res
+        );
+    }
+
+    #[test]
+    fn http_10_stream_closes_connection() {
+        let server = start(stream);
+        let res = send(&server, "GET / HTTP/1.0\r\nConnection: keep-alive\r\n\r\n");
+
+        assert!(!res.contains("transfer-encoding"), "{}", res);
+        assert!(res.contains("\r\nconnection: close\r\n"), "{}", res);
+        assert!(res.ends_with("\r\n\r\nstreamed body"), "{}", res);
+    }
+
+    #[test]
+    fn head_has_no_body() {
+        let server = start(echo);
+        let res = send(&server, "HEAD /foo HTTP/1.1\r\nConnection: close\r\n\r\n");
+
+        assert!(res.contains("\r\ncontent-length: 15\r\n"), "{}", res);
+        assert!(res.ends_with("\r\n\r\n"), "{}", res);
+    }
+
+    #[test]
+    fn handler_error() {
+        let server = start(failing);
+        let res = send(&server, "GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+
+        assert!(
+            res.starts_with("HTTP/1.1 500 Internal Server Error\r\n"),
+            "{}",
+            res
+        );
+        assert!(res.ends_with("Internal Server Error"), "{}", res);
+    }
+
+    #[test]
+    fn handler_panic() {
+        let server = start(panicking);
+        // More panics than worker threads
+        for _ in 0..3 {
+            let res = send(&server, "GET / HTTP/1.1\r\n\r\n");
+            assert!(
+                res.starts_with("HTTP/1.1 500 Internal Server Error\r\n"),
+                "{}",
+                res
+            );
+            assert!(res.contains("\r\nconnection: close\r\n"), "{}", res);
+        }
+    }
+
+    #[test]
+    fn bad_request() {
+        let server = start(echo);
+        let res = send(&server, "NOT HTTP\r\n\r\n");
+
+        assert!(res.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", res);
+        assert!(res.contains("\r\nconnection: close\r\n"), "{}", res);
+    }
+
+    #[test]
+    fn conflicting_content_length() {
+        let server = start(echo);
+        let res = send(
+            &server,
+            "POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 4\r\n\r\n",
+        );
+
+        assert!(res.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", res);
+        assert!(res.contains("\r\nconnection: close\r\n"), "{}", res);
+    }
+
+    #[test]
+    fn content_length_with_transfer_encoding() {
+        let server = start(echo);
+        let res = send(
+            &server,
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n",
+        );
+
+        assert!(res.starts_with("HTTP/1.1 400 Bad Request\r\n"), "{}", res);
+        assert!(res.contains("\r\nconnection: close\r\n"), "{}", res);
+    }
+
+    #[test]
+    fn host_header() {
+        let server = start(|req: &mut dyn RequestExt| {
+            let host = format!("{:?}", req.host());
+            let response = Response::builder().header(header::CONTENT_TYPE, "text/plain");
+            response.body(Body::from_vec(host.into_bytes()))
+        });
+        let res = send(
+            &server,
+            "GET / HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n",
+        );
+
+        assert!(res.ends_with("Name(\"example.com\")"), "{}", res);
+    }
+}
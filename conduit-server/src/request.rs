@@ -0,0 +1,441 @@
+use std::io::{self, BufRead, Read, Take, Write};
+use std::net::SocketAddr;
+
+use conduit::header::{self, HeaderName, HeaderValue};
+use conduit::{Extensions, HeaderMap, Host, Method, Scheme, StartInstant, Version};
+
+const MAX_HEAD_LENGTH: usize = 64 * 1024;
+const MAX_HEADERS: usize = 100;
+const MAX_DRAIN_LENGTH: u64 = 64 * 1024;
+
+/// An error encountered while reading a request head from a connection.
+#[derive(Debug)]
+pub(crate) enum ParseError {
+    /// The connection was closed before any bytes of a new request arrived.
+    Closed,
+    /// The request head could not be parsed, the connection must be closed.
+    Invalid,
+    Io(io::Error),
+}
+
+impl From<io::Error> for ParseError {
+    fn from(error: io::Error) -> Self {
+        ParseError::Io(error)
+    }
+}
+
+/// A request read from a connection, implementing `conduit::RequestExt`.
+pub(crate) struct ServerRequest<'a> {
+    version: Version,
+    method: Method,
+    path: String,
+    query_string: Option<String>,
+    headers: HeaderMap,
+    local_addr: SocketAddr,
+    remote_addr: SocketAddr,
+    body: RequestBody<'a>,
+    extensions: Extensions,
+}
+
+impl<'a> ServerRequest<'a> {
+    /// Read a request head from `reader`, leaving the body unread.
+    pub(crate) fn read<R: BufRead>(
+        reader: &'a mut R,
+        local_addr: SocketAddr,
+        remote_addr: SocketAddr,
+    ) -> Result<Self, ParseError> {
+        let head = read_head(reader)?;
+        // Capture the instant as soon as the headers have been received.
+        let start = StartInstant::now();
+
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut parsed = httparse::Request::new(&mut headers);
+        match parsed.parse(&head) {
+            Ok(httparse::Status::Complete(_)) => {}
+            _ => return Err(ParseError::Invalid),
+        }
+
+        let version = match parsed.version {
+            Some(0) => Version::HTTP_10,
+            Some(1) => Version::HTTP_11,
+            _ => return Err(ParseError::Invalid),
+        };
+        let method = parsed
+            .method
+            .and_then(|method| Method::from_bytes(method.as_bytes()).ok())
+            .ok_or(ParseError::Invalid)?;
+        let (path, query_string) = split_target(parsed.path.ok_or(ParseError::Invalid)?)?;
+
+        let mut header_map = HeaderMap::with_capacity(parsed.headers.len());
+        for h in parsed.headers.iter() {
+            let name =
+                HeaderName::from_bytes(h.name.as_bytes()).map_err(|_| ParseError::Invalid)?;
+            let value = HeaderValue::from_bytes(h.value).map_err(|_| ParseError::Invalid)?;
+            header_map.append(name, value);
+        }
+
+        let body = RequestBody::new(reader, &header_map)?;
+
+        let mut extensions = Extensions::new();
+        extensions.insert(start);
+
+        Ok(ServerRequest {
+            version,
+            method,
+            path,
+            query_string,
+            headers: header_map,
+            local_addr,
+            remote_addr,
+            body,
+            extensions,
+        })
+    }
+
+    /// Whether the client asked for the connection to stay open after this
+    /// request.
+    pub(crate) fn wants_keep_alive(&self) -> bool {
+        if has_token(&self.headers, header::CONNECTION, "close") {
+            return false;
+        }
+        self.version == Version::HTTP_11
+            || has_token(&self.headers, header::CONNECTION, "keep-alive")
+    }
+
+    /// Whether the client sent `Expect: 100-continue`.
+    pub(crate) fn expects_continue(&self) -> bool {
+        self.version == Version::HTTP_11 && has_token(&self.headers, header::EXPECT, "100-continue")
+    }
+
+    /// Read and discard any part of the body the handler did not consume, so
+    /// that the next request can be read from the connection.
+    ///
+    /// Fails if more than `MAX_DRAIN_LENGTH` bytes are left, in which case the
+    /// connection should be closed instead.
+    pub(crate) fn drain_body(&mut self) -> io::Result<()> {
+        let mut body = (&mut self.body).take(MAX_DRAIN_LENGTH + 1);
+        if io::copy(&mut body, &mut io::sink())? > MAX_DRAIN_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "unread request body is too large to drain",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<'a> conduit::RequestExt for ServerRequest<'a> {
+    fn http_version(&self) -> Version {
+        self.version
+    }
+
+    fn method(&self) -> &Method {
+        &self.method
+    }
+
+    fn scheme(&self) -> Scheme {
+        Scheme::Http
+    }
+
+    fn host(&self) -> Host<'_> {
+        match self.headers.get(header::HOST).map(HeaderValue::to_str) {
+            Some(Ok(host)) => Host::Name(host),
+            _ => Host::Socket(self.local_addr),
+        }
+    }
+
+    fn virtual_root(&self) -> Option<&str> {
+        None
+    }
+
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn path_mut(&mut self) -> &mut String {
+        &mut self.path
+    }
+
+    fn query_string(&self) -> Option<&str> {
+        self.query_string.as_deref()
+    }
+
+    fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        content_length(&self.headers)
+    }
+
+    fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    fn body(&mut self) -> &mut dyn Read {
+        &mut self.body
+    }
+
+    fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    fn mut_extensions(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+}
+
+/// Read bytes up to and including the blank line ending the request head.
+fn read_head<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, ParseError> {
+    let mut head = Vec::new();
+
+    loop {
+        // Read at most one byte past the limit, so that a line without a
+        // newline can't grow the head indefinitely
+        let limit = (MAX_HEAD_LENGTH + 1 - head.len()) as u64;
+        let read = (&mut *reader).take(limit).read_until(b'\n', &mut head)?;
+        if read == 0 {
+            return Err(if head.is_empty() {
+                ParseError::Closed
+            } else {
+                ParseError::Invalid
+            });
+        }
+        if head.len() > MAX_HEAD_LENGTH {
+            return Err(ParseError::Invalid);
+        }
+        // RFC 7230 §3.5: ignore empty lines received before the request-line
+        if head == b"\r\n" || head == b"\n" {
+            head.clear();
+            continue;
+        }
+        if head.ends_with(b"\r\n\r\n") || head.ends_with(b"\n\n") {
+            return Ok(head);
+        }
+    }
+}
+
+/// Split a request-target into its path and query string.
+fn split_target(target: &str) -> Result<(String, Option<String>), ParseError> {
+    // absolute-form, as sent to proxies: `http://example.com/path?query`
+    let target = match target.find("://") {
+        Some(i) => {
+            let rest = &target[i + 3..];
+            rest.find('/').map(|j| &rest[j..]).unwrap_or("/")
+        }
+        None => target,
+    };
+
+    if !target.starts_with('/') && target != "*" {
+        return Err(ParseError::Invalid);
+    }
+
+    Ok(match target.find('?') {
+        Some(i) => (target[..i].to_string(), Some(target[i + 1..].to_string())),
+        None => (target.to_string(), None),
+    })
+}
+
+/// The value of the Content-Length header, or `None` if it is missing,
+/// invalid or repeated with differing values (RFC 7230 §3.3.2).
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    let mut length = None;
+    for value in headers.get_all(header::CONTENT_LENGTH) {
+        for element in value.to_str().ok()?.split(',') {
+            let element = element.trim().parse().ok()?;
+            if length.map_or(false, |length| length != element) {
+                return None;
+            }
+            length = Some(element);
+        }
+    }
+    length
+}
+
+/// Whether any comma separated element of the header matches `token`,
+/// ignoring case.
+pub(crate) fn has_token(headers: &HeaderMap, name: HeaderName, token: &str) -> bool {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|element| element.trim().eq_ignore_ascii_case(token))
+}
+
+/// The body of a request, framed according to its headers.
+enum RequestBody<'a> {
+    Empty,
+    Length(Take<&'a mut dyn BufRead>),
+    Chunked(ChunkedReader<'a>),
+}
+
+impl<'a> RequestBody<'a> {
+    fn new<R: BufRead>(reader: &'a mut R, headers: &HeaderMap) -> Result<Self, ParseError> {
+        let reader = reader as &mut dyn BufRead;
+
+        if headers.contains_key(header::TRANSFER_ENCODING) {
+            // RFC 7230 §3.3.3: a message with both headers may be an attempt
+            // at request smuggling
+            if headers.contains_key(header::CONTENT_LENGTH) {
+                return Err(ParseError::Invalid);
+            }
+
+            // RFC 7230 §3.3.3: chunked must be the final encoding, anything
+            // else leaves the body length undeterminable.
+            let chunked = headers
+                .get_all(header::TRANSFER_ENCODING)
+                .iter()
+                .next_back()
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.rsplit(',').next())
+                .map_or(false, |coding| {
+                    coding.trim().eq_ignore_ascii_case("chunked")
+                });
+            if !chunked {
+                return Err(ParseError::Invalid);
+            }
+            return Ok(RequestBody::Chunked(ChunkedReader::new(reader)));
+        }
+
+        if headers.contains_key(header::CONTENT_LENGTH) {
+            let length = content_length(headers).ok_or(ParseError::Invalid)?;
+            return Ok(RequestBody::Length(reader.take(length)));
+        }
+
+        Ok(RequestBody::Empty)
+    }
+}
+
+impl<'a> Read for RequestBody<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            RequestBody::Empty => Ok(0),
+            RequestBody::Length(reader) => reader.read(buf),
+            RequestBody::Chunked(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// A reader decoding the chunked transfer coding (RFC 7230 §4.1).
+struct ChunkedReader<'a> {
+    reader: &'a mut dyn BufRead,
+    remaining: u64,
+    done: bool,
+}
+
+impl<'a> ChunkedReader<'a> {
+    fn new(reader: &'a mut dyn BufRead) -> Self {
+        ChunkedReader {
+            reader,
+            remaining: 0,
+            done: false,
+        }
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = Vec::new();
+        (&mut self.reader).take(4096).read_until(b'\n', &mut line)?;
+        if !line.ends_with(b"\n") {
+            return Err(invalid_chunk());
+        }
+        String::from_utf8(line).map_err(|_| invalid_chunk())
+    }
+
+    fn read_chunk_size(&mut self) -> io::Result<u64> {
+        let line = self.read_line()?;
+        // Chunk extensions are ignored
+        let size = line.split(';').next().unwrap_or("").trim();
+        u64::from_str_radix(size, 16).map_err(|_| invalid_chunk())
+    }
+}
+
+impl<'a> Read for ChunkedReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+
+        if self.remaining == 0 {
+            self.remaining = self.read_chunk_size()?;
+            if self.remaining == 0 {
+                // Skip the trailer section up to the final empty line
+                while !self.read_line()?.trim().is_empty() {}
+                self.done = true;
+                return Ok(0);
+            }
+        }
+
+        let max = std::cmp::min(buf.len() as u64, self.remaining) as usize;
+        let read = self.reader.read(&mut buf[..max])?;
+        if read == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.remaining -= read as u64;
+
+        if self.remaining == 0 && self.read_line()?.trim() != "" {
+            return Err(invalid_chunk());
+        }
+
+        Ok(read)
+    }
+}
+
+fn invalid_chunk() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid chunked encoding")
+}
+
+/// Write an interim `100 Continue` response.
+pub(crate) fn write_continue<W: Write>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, BufReader, Read};
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    use conduit::header::{self, HeaderValue};
+    use conduit::HeaderMap;
+
+    use super::{content_length, read_head, ParseError, ServerRequest, MAX_HEAD_LENGTH};
+
+    #[test]
+    fn head_without_newline_is_bounded() {
+        let mut reader = BufReader::new(io::repeat(b'a'));
+        assert!(matches!(read_head(&mut reader), Err(ParseError::Invalid)));
+
+        let mut reader = BufReader::new(io::repeat(b'a').take(MAX_HEAD_LENGTH as u64 * 2));
+        assert!(matches!(read_head(&mut reader), Err(ParseError::Invalid)));
+    }
+
+    #[test]
+    fn head() {
+        let mut reader = BufReader::new(&b"\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\nbody"[..]);
+        let head = read_head(&mut reader).unwrap();
+        assert_eq!(head, b"GET / HTTP/1.1\r\nHost: a\r\n\r\n");
+    }
+
+    #[test]
+    fn repeated_content_length() {
+        let mut headers = HeaderMap::new();
+        headers.append(header::CONTENT_LENGTH, HeaderValue::from_static("3"));
+        headers.append(header::CONTENT_LENGTH, HeaderValue::from_static("3, 3"));
+        assert_eq!(content_length(&headers), Some(3));
+
+        headers.append(header::CONTENT_LENGTH, HeaderValue::from_static("4"));
+        assert_eq!(content_length(&headers), None);
+    }
+
+    #[test]
+    fn large_unread_body_is_not_drained() {
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 80));
+        let mut data = b"POST / HTTP/1.1\r\nContent-Length: 1000000\r\n\r\n".to_vec();
+        data.resize(data.len() + 1_000_000, b'a');
+
+        let mut reader = &data[..];
+        let mut request = ServerRequest::read(&mut reader, addr, addr).unwrap();
+        assert!(request.drain_body().is_err());
+    }
+}
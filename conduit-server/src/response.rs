@@ -0,0 +1,152 @@
+use std::io::{self, Read, Write};
+use std::time::SystemTime;
+
+use conduit::header::{self, HeaderValue};
+use conduit::{Body, Method, Response, StatusCode, Version};
+use conduit_httpdate::fmt_http_date;
+
+use crate::request::has_token;
+
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// How the body of a response is delimited on the wire.
+enum Framing {
+    /// No body is sent, as for `HEAD` requests and `204`/`304` responses.
+    None,
+    Length(u64),
+    Chunked,
+    /// The body ends when the connection is closed (HTTP/1.0 only).
+    Close,
+}
+
+/// Write `response` to `writer`, returning whether the connection may be
+/// reused for another request.
+pub(crate) fn write_response<W: Write>(
+    writer: &mut W,
+    method: &Method,
+    version: Version,
+    keep_alive: bool,
+    response: Response<Body>,
+) -> io::Result<bool> {
+    let (mut parts, body) = response.into_parts();
+    let mut keep_alive = keep_alive && !has_token(&parts.headers, header::CONNECTION, "close");
+
+    let headers = &mut parts.headers;
+    let status = parts.status;
+    let bodiless = *method == Method::HEAD
+        || status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED;
+
+    let declared = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    let length = match &body {
+        Body::Static(slice) => Some(slice.len() as u64),
+        Body::Owned(vec) => Some(vec.len() as u64),
+        Body::File(file) => declared.or(file.metadata().ok().map(|data| data.len())),
+        Body::Stream(_) => declared,
+    };
+
+    let framing = if bodiless {
+        Framing::None
+    } else if let Some(length) = length {
+        Framing::Length(length)
+    } else if version == Version::HTTP_11 {
+        Framing::Chunked
+    } else {
+        keep_alive = false;
+        Framing::Close
+    };
+
+    match framing {
+        Framing::Length(length) => {
+            headers.insert(header::CONTENT_LENGTH, length.into());
+            headers.remove(header::TRANSFER_ENCODING);
+        }
+        Framing::Chunked => {
+            headers.remove(header::CONTENT_LENGTH);
+            headers.insert(
+                header::TRANSFER_ENCODING,
+                HeaderValue::from_static("chunked"),
+            );
+        }
+        Framing::None
+            if *method == Method::HEAD && !headers.contains_key(header::CONTENT_LENGTH) =>
+        {
+            if let Some(length) = length {
+                headers.insert(header::CONTENT_LENGTH, length.into());
+            }
+        }
+        Framing::None | Framing::Close => {}
+    }
+
+    if !headers.contains_key(header::DATE) {
+        let date = fmt_http_date(SystemTime::now());
+        headers.insert(header::DATE, HeaderValue::from_str(&date).unwrap());
+    }
+    if !keep_alive {
+        headers.insert(header::CONNECTION, HeaderValue::from_static("close"));
+    } else if version == Version::HTTP_10 {
+        headers.insert(header::CONNECTION, HeaderValue::from_static("keep-alive"));
+    }
+
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\n",
+        status.as_str(),
+        status.canonical_reason().unwrap_or("")
+    )?;
+    for (name, value) in headers.iter() {
+        writer.write_all(name.as_str().as_bytes())?;
+        writer.write_all(b": ")?;
+        writer.write_all(value.as_bytes())?;
+        writer.write_all(b"\r\n")?;
+    }
+    writer.write_all(b"\r\n")?;
+
+    match framing {
+        Framing::None => {}
+        Framing::Length(length) => {
+            let written = io::copy(&mut body_reader(body).take(length), writer)?;
+            if written < length {
+                // The body was shorter than advertised, the client can only
+                // detect this if the connection is closed.
+                keep_alive = false;
+            }
+        }
+        Framing::Chunked => write_chunked(writer, body_reader(body))?,
+        Framing::Close => {
+            io::copy(&mut body_reader(body), writer)?;
+        }
+    }
+
+    writer.flush()?;
+    Ok(keep_alive)
+}
+
+fn body_reader(body: Body) -> Box<dyn Read + Send> {
+    match body {
+        Body::Static(slice) => Box::new(slice),
+        Body::Owned(vec) => Box::new(io::Cursor::new(vec)),
+        Body::File(file) => Box::new(file),
+        Body::Stream(reader) => reader,
+    }
+}
+
+fn write_chunked<W: Write>(writer: &mut W, mut reader: Box<dyn Read + Send>) -> io::Result<()> {
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let read = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        write!(writer, "{:X}\r\n", read)?;
+        writer.write_all(&buf[..read])?;
+        writer.write_all(b"\r\n")?;
+    }
+    writer.write_all(b"0\r\n\r\n")
+}
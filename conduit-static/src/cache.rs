@@ -0,0 +1,184 @@
+//! Rule-based `Cache-Control` and `Expires` headers.
+
+use std::time::{Duration, SystemTime};
+
+use conduit::header::{HeaderValue, InvalidHeaderValue};
+use globset::{GlobBuilder, GlobMatcher};
+use regex::Regex;
+
+/// Matches file names containing a hexadecimal content hash of at least
+/// eight digits, such as `app.3f2a1b9c.js` or `logo-0123abcd.png`.
+const FINGERPRINT: &str = r"[.-][0-9a-fA-F]{8,}\.[^.]+$";
+
+/// Selects the files a `CachePolicy` applies to.
+///
+/// Rules are matched against the path of the served file relative to the
+/// root directory, without a leading slash, such as `assets/app.js`.
+#[derive(Clone, Debug)]
+pub struct CacheRule {
+    matcher: Matcher,
+}
+
+#[derive(Clone, Debug)]
+enum Matcher {
+    Glob(GlobMatcher),
+    Extension(String),
+    FileName(Regex),
+}
+
+impl CacheRule {
+    /// Match paths against a glob pattern, such as `assets/**/*.css`.
+    ///
+    /// `*` and `?` don't match `/`, use `**` to match across directories.
+    pub fn glob(pattern: &str) -> Result<CacheRule, globset::Error> {
+        let glob = GlobBuilder::new(pattern).literal_separator(true).build()?;
+        Ok(CacheRule {
+            matcher: Matcher::Glob(glob.compile_matcher()),
+        })
+    }
+
+    /// Match files with the extension, such as `html`, ignoring case.
+    pub fn extension(extension: &str) -> CacheRule {
+        CacheRule {
+            matcher: Matcher::Extension(extension.trim_start_matches('.').to_string()),
+        }
+    }
+
+    /// Match file names containing a content hash, such as `app.3f2a1b9c.js`.
+    pub fn fingerprinted() -> CacheRule {
+        CacheRule {
+            matcher: Matcher::FileName(Regex::new(FINGERPRINT).unwrap()),
+        }
+    }
+
+    /// Match file names against a regular expression, for fingerprints in a
+    /// format not recognized by `CacheRule::fingerprinted`.
+    pub fn file_name(pattern: &str) -> Result<CacheRule, regex::Error> {
+        Ok(CacheRule {
+            matcher: Matcher::FileName(Regex::new(pattern)?),
+        })
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        match &self.matcher {
+            Matcher::Glob(glob) => glob.is_match(path),
+            Matcher::Extension(extension) => name
+                .rsplit_once('.')
+                .map_or(false, |(_, ext)| ext.eq_ignore_ascii_case(extension)),
+            Matcher::FileName(regex) => regex.is_match(name),
+        }
+    }
+}
+
+/// The caching headers sent for files matched by a `CacheRule`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachePolicy {
+    cache_control: HeaderValue,
+    expires: Option<Duration>,
+}
+
+impl CachePolicy {
+    /// Send `cache_control` as the `Cache-Control` header.
+    ///
+    /// Fails if `cache_control` isn't a valid header value, such as a value
+    /// with control characters.
+    pub fn new(cache_control: &str) -> Result<CachePolicy, InvalidHeaderValue> {
+        let cache_control = HeaderValue::from_str(cache_control)?;
+        Ok(CachePolicy::from_header(cache_control))
+    }
+
+    /// Cache for a year without revalidating, for files whose name changes
+    /// along with their contents.
+    pub fn immutable() -> CachePolicy {
+        CachePolicy::from_header(HeaderValue::from_static(
+            "public, max-age=31536000, immutable",
+        ))
+    }
+
+    /// Allow caching, but revalidate before every use.
+    pub fn no_cache() -> CachePolicy {
+        CachePolicy::from_header(HeaderValue::from_static("no-cache"))
+    }
+
+    fn from_header(cache_control: HeaderValue) -> CachePolicy {
+        CachePolicy {
+            cache_control,
+            expires: None,
+        }
+    }
+
+    /// Also send an `Expires` header, `ttl` after the time of the response,
+    /// for HTTP/1.0 caches.
+    pub fn expires(mut self, ttl: Duration) -> CachePolicy {
+        self.expires = Some(ttl);
+        self
+    }
+
+    pub(crate) fn cache_control(&self) -> &HeaderValue {
+        &self.cache_control
+    }
+
+    pub(crate) fn expires_at(&self, now: SystemTime) -> Option<SystemTime> {
+        self.expires.map(|ttl| now + ttl)
+    }
+}
+
+/// Find the policy of the first rule matching `path`.
+pub(crate) fn policy_for<'a>(
+    rules: &'a [(CacheRule, CachePolicy)],
+    path: &str,
+) -> Option<&'a CachePolicy> {
+    rules
+        .iter()
+        .find(|(rule, _)| rule.matches(path))
+        .map(|(_, policy)| policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CachePolicy, CacheRule};
+
+    #[test]
+    fn invalid_policy() {
+        assert!(CachePolicy::new("max-age=60\r\nSet-Cookie: a=b").is_err());
+        assert!(CachePolicy::new("max-age=60").is_ok());
+    }
+
+    #[test]
+    fn glob() {
+        let rule = CacheRule::glob("assets/**/*.css").unwrap();
+        assert!(rule.matches("assets/site.css"));
+        assert!(rule.matches("assets/vendor/lib.css"));
+        assert!(!rule.matches("site.css"));
+
+        let rule = CacheRule::glob("*.css").unwrap();
+        assert!(rule.matches("site.css"));
+        assert!(!rule.matches("assets/site.css"));
+
+        assert!(CacheRule::glob("a[").is_err());
+    }
+
+    #[test]
+    fn extension() {
+        let rule = CacheRule::extension("html");
+        assert!(rule.matches("index.html"));
+        assert!(rule.matches("docs/INDEX.HTML"));
+        assert!(!rule.matches("index.htm"));
+        assert!(!rule.matches("html"));
+        assert_eq!(
+            CacheRule::extension(".html").matches("a.html"),
+            rule.matches("a.html")
+        );
+    }
+
+    #[test]
+    fn fingerprinted() {
+        let rule = CacheRule::fingerprinted();
+        assert!(rule.matches("app.3f2a1b9c.js"));
+        assert!(rule.matches("img/logo-0123abcdef.png"));
+        assert!(!rule.matches("app.js"));
+        assert!(!rule.matches("app.3f2a.js"));
+        assert!(!rule.matches("3f2a1b9c.d/app.js"));
+    }
+}
@@ -0,0 +1,332 @@
+//! Serving files compiled into the binary.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::SystemTime;
+
+use conduit::{Body, Handler, HandlerResult, HeaderMap, RequestExt};
+use conduit_conditional_get::content_hash;
+use conduit_httpdate::fmt_http_date;
+use conduit_mime_types as mime;
+
+use crate::cache::{self, CachePolicy, CacheRule};
+use crate::encoding::{self, Encoding};
+use crate::etag::format_etag;
+use crate::path::RequestPath;
+use crate::{lookup_path, range, Entry, FileHeaders, Files};
+
+struct Asset {
+    contents: &'static [u8],
+    mime: String,
+    hash: String,
+}
+
+/// A handler serving files embedded in the binary, typically with
+/// `include_bytes!`.
+///
+/// Responses carry the same headers as those of `Static`. The `ETag` is a
+/// hash of the contents, and no `Last-Modified` date is sent unless one is
+/// set with `Embedded::last_modified`.
+///
+/// Files are hashed at runtime, when they are added. Embedding whole
+/// directories or hashing at build time is out of scope.
+///
+/// ```
+/// use conduit_static::Embedded;
+///
+/// let mut assets = Embedded::new();
+/// assets
+///     .add("robots.txt", b"User-agent: *\n")
+///     .add("index.html", b"<h1>Hello</h1>")
+///     .index_file("index.html");
+/// ```
+pub struct Embedded {
+    assets: HashMap<String, Asset>,
+    last_modified: Option<String>,
+    weak_etags: bool,
+    index_files: Vec<String>,
+    encodings: Vec<Encoding>,
+    cache: Vec<(CacheRule, CachePolicy)>,
+}
+
+impl Embedded {
+    pub fn new() -> Embedded {
+        Embedded {
+            assets: HashMap::new(),
+            last_modified: None,
+            weak_etags: false,
+            index_files: Vec::new(),
+            encodings: Vec::new(),
+            cache: Vec::new(),
+        }
+    }
+
+    /// Serve `contents` at `path`, such as `assets/app.js`.
+    ///
+    /// The MIME type is derived from the extension of `path`. Precompressed
+    /// variants are added like any other file, such as `assets/app.js.gz`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `path` would resolve outside of the root, such as `../app.js`.
+    pub fn add(&mut self, path: &str, contents: &'static [u8]) -> &mut Embedded {
+        let key = match RequestPath::parse(path) {
+            Some(key) if !key.is_root() => key.relative(),
+            _ => panic!("invalid path of embedded file: {:?}", path),
+        };
+        let asset = Asset {
+            contents,
+            mime: mime::mime_for_path(Path::new(&key))
+                .unwrap_or("application/octet-stream")
+                .to_string(),
+            hash: content_hash(contents),
+        };
+        self.assets.insert(key, asset);
+        self
+    }
+
+    /// Set the `Last-Modified` date of all files.
+    pub fn last_modified(&mut self, time: SystemTime) -> &mut Embedded {
+        self.last_modified = Some(fmt_http_date(time));
+        self
+    }
+
+    /// Send weak (`W/"..."`) instead of strong entity tags.
+    pub fn weak_etags(&mut self, weak: bool) -> &mut Embedded {
+        self.weak_etags = weak;
+        self
+    }
+
+    /// Add a file name, such as `index.html`, to serve for requests of a
+    /// directory.
+    pub fn index_file(&mut self, name: &str) -> &mut Embedded {
+        self.index_files.push(name.to_string());
+        self
+    }
+
+    /// Serve precompressed variants to clients accepting `encoding`, see
+    /// `Static::precompressed`.
+    pub fn precompressed(&mut self, encoding: Encoding) -> &mut Embedded {
+        self.encodings.push(encoding);
+        self
+    }
+
+    /// Send the caching headers of `policy` for files matching `rule`, see
+    /// `Static::cache`.
+    pub fn cache(&mut self, rule: CacheRule, policy: CachePolicy) -> &mut Embedded {
+        self.cache.push((rule, policy));
+        self
+    }
+
+    /// Look up the file at `request_path`.
+    pub fn lookup(&self, request_path: &str) -> HandlerResult {
+        lookup_path(self, request_path, None, &HeaderMap::new())
+    }
+}
+
+impl Files for Embedded {
+    type Key = String;
+
+    fn find(&self, path: &RequestPath) -> Option<Entry<String>> {
+        // Any other path is a directory, which is only served if it has an
+        // index file
+        let key = path.relative();
+        if self.assets.contains_key(&key) {
+            Some(Entry::File(key))
+        } else {
+            Some(Entry::Dir(key))
+        }
+    }
+
+    fn index(&self, dir: &String) -> Option<String> {
+        self.index_files
+            .iter()
+            .map(|name| {
+                if dir.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}/{}", dir, name)
+                }
+            })
+            .find(|index| self.assets.contains_key(index))
+    }
+
+    fn respond(&self, key: &String, headers: &HeaderMap) -> HandlerResult {
+        let original = &self.assets[key];
+        let variant = encoding::negotiate(Path::new(key), &self.encodings, headers, |path| {
+            path.to_str()
+                .map_or(false, |path| self.assets.contains_key(path))
+        });
+        let asset = variant
+            .path
+            .to_str()
+            .and_then(|path| self.assets.get(path))
+            .unwrap_or(original);
+
+        FileHeaders {
+            mime: &original.mime,
+            len: asset.contents.len() as u64,
+            last_modified: self.last_modified.clone(),
+            etag: Some(format_etag(&asset.hash, self.weak_etags)),
+            variant: &variant,
+            cache: cache::policy_for(&self.cache, key),
+        }
+        .respond(Body::Static(asset.contents))
+    }
+}
+
+impl Default for Embedded {
+    fn default() -> Embedded {
+        Embedded::new()
+    }
+}
+
+impl Handler for Embedded {
+    fn call(&self, request: &mut dyn RequestExt) -> HandlerResult {
+        let headers = request.headers();
+        let response = lookup_path(self, request.path(), request.query_string(), headers)?;
+        range::respond(request, response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use conduit::{header, Handler, Method, StatusCode};
+    use conduit_conditional_get::ConditionalGet;
+    use conduit_middleware::MiddlewareBuilder;
+    use conduit_test::{MockRequest, ResponseExt};
+
+    use crate::{CachePolicy, CacheRule, Embedded, Encoding};
+
+    fn assets() -> Embedded {
+        let mut assets = Embedded::new();
+        assets
+            .add("/index.html", b"<h1>home</h1>")
+            .add("docs/index.html", b"<h1>docs</h1>")
+            .add("app.js", b"console.log('hello');")
+            .add("app.js.gz", b"gzipped")
+            .index_file("index.html")
+            .precompressed(Encoding::Gzip);
+        assets
+    }
+
+    #[test]
+    fn serve() {
+        let handler = assets();
+        let mut req = MockRequest::new(Method::GET, "/app.js");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "application/javascript"
+        );
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "21");
+        assert_eq!(res.headers()[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(res.headers()[header::VARY], "Accept-Encoding");
+        assert!(!res.headers().contains_key(header::LAST_MODIFIED));
+        assert!(res.headers().contains_key(header::ETAG));
+        assert_eq!(*res.into_cow(), b"console.log('hello');"[..]);
+
+        let mut req = MockRequest::new(Method::GET, "/missing.js");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn precompressed() {
+        let handler = assets();
+        let mut req = MockRequest::new(Method::GET, "/app.js");
+        req.header(header::ACCEPT_ENCODING, "gzip");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "application/javascript"
+        );
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(*res.into_cow(), b"gzipped"[..]);
+    }
+
+    #[test]
+    fn index_files() {
+        let handler = assets();
+        let mut req = MockRequest::new(Method::GET, "/");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(*res.into_cow(), b"<h1>home</h1>"[..]);
+
+        let mut req = MockRequest::new(Method::GET, "/docs/");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/html");
+        assert_eq!(*res.into_cow(), b"<h1>docs</h1>"[..]);
+
+        let mut req = MockRequest::new(Method::GET, "/docs");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(res.headers()[header::LOCATION], "docs/");
+
+        let mut req = MockRequest::new(Method::GET, "/app.js/");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn last_modified_and_cache() {
+        let mut handler = assets();
+        handler
+            .last_modified(UNIX_EPOCH + Duration::from_secs(784_111_777))
+            .cache(CacheRule::extension("js"), CachePolicy::immutable());
+        let mut req = MockRequest::new(Method::GET, "/app.js");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(
+            res.headers()[header::LAST_MODIFIED],
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+        assert_eq!(
+            res.headers()[header::CACHE_CONTROL],
+            "public, max-age=31536000, immutable"
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let handler = assets();
+        let mut req = MockRequest::new(Method::GET, "/app.js");
+        req.header(header::RANGE, "bytes=0-6");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 0-6/21");
+        assert_eq!(*res.into_cow(), b"console"[..]);
+
+        // `If-Range` dates are compared whatever their format
+        let mut handler = assets();
+        handler.last_modified(UNIX_EPOCH + Duration::from_secs(784_111_777));
+        let mut req = MockRequest::new(Method::GET, "/app.js");
+        req.header(header::RANGE, "bytes=0-6");
+        req.header(header::IF_RANGE, "Sun Nov  6 08:49:37 1994");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    }
+
+    #[test]
+    fn conditional_get() {
+        let mut stack = MiddlewareBuilder::new(assets());
+        stack.add(ConditionalGet);
+
+        let mut req = MockRequest::new(Method::GET, "/app.js");
+        let res = stack.call(&mut req).expect("No response");
+        let etag = res.headers()[header::ETAG].clone();
+        assert_eq!(etag.len(), 34);
+
+        let mut req = MockRequest::new(Method::GET, "/app.js");
+        req.header(header::IF_NONE_MATCH, etag.to_str().unwrap());
+        let res = stack.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid path")]
+    fn invalid_path() {
+        Embedded::new().add("../secret", b"");
+    }
+}
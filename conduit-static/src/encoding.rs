@@ -0,0 +1,188 @@
+//! Negotiation of precompressed file variants.
+
+use std::path::{Path, PathBuf};
+
+use conduit::header::{self, HeaderMap};
+
+/// A content coding of a precompressed sibling file, such as `app.js.gz`
+/// for `app.js`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// `br`, stored with the `.br` extension.
+    Brotli,
+    /// `gzip`, stored with the `.gz` extension.
+    Gzip,
+    /// `zstd`, stored with the `.zst` extension.
+    Zstd,
+}
+
+impl Encoding {
+    /// The content coding, as used in `Accept-Encoding` and
+    /// `Content-Encoding` headers.
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    /// The extension appended to the name of the original file.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+            Encoding::Zstd => "zst",
+        }
+    }
+
+    fn matches(self, coding: &str) -> bool {
+        coding.eq_ignore_ascii_case(self.name())
+            || (self == Encoding::Gzip && coding.eq_ignore_ascii_case("x-gzip"))
+    }
+
+    fn sibling(self, path: &Path) -> Option<PathBuf> {
+        let mut name = path.file_name()?.to_os_string();
+        name.push(".");
+        name.push(self.extension());
+        Some(path.with_file_name(name))
+    }
+}
+
+/// The result of negotiating which variant of a file to serve.
+pub(crate) struct Variant {
+    pub(crate) path: PathBuf,
+    pub(crate) encoding: Option<Encoding>,
+    /// Whether other variants exist, so that the response must carry
+    /// `Vary: Accept-Encoding`.
+    pub(crate) varies: bool,
+}
+
+/// Pick the variant of `path` to serve, given the encodings configured in
+/// order of preference and the request headers.
+///
+/// `exists` tells whether a sibling variant is available.
+pub(crate) fn negotiate(
+    path: &Path,
+    encodings: &[Encoding],
+    headers: &HeaderMap,
+    exists: impl Fn(&Path) -> bool,
+) -> Variant {
+    let available: Vec<_> = encodings
+        .iter()
+        .filter_map(|&encoding| Some((encoding, encoding.sibling(path)?)))
+        .filter(|(_, sibling)| exists(sibling))
+        .collect();
+
+    let identity = Variant {
+        path: path.to_path_buf(),
+        encoding: None,
+        varies: !available.is_empty(),
+    };
+    if available.is_empty() {
+        return identity;
+    }
+
+    let accepted = accepted_codings(headers);
+    let identity_q = qvalue(&accepted, "identity").unwrap_or(1000);
+
+    let mut best: Option<(u16, Encoding, PathBuf)> = None;
+    for (encoding, sibling) in available {
+        let q = qvalue_for(&accepted, encoding).unwrap_or(0);
+        // Ties are resolved in favour of the earlier, preferred encoding
+        if q > 0 && best.as_ref().map_or(true, |(best_q, ..)| q > *best_q) {
+            best = Some((q, encoding, sibling));
+        }
+    }
+
+    match best {
+        Some((q, encoding, path)) if q >= identity_q => Variant {
+            path,
+            encoding: Some(encoding),
+            varies: true,
+        },
+        _ => identity,
+    }
+}
+
+/// Parse the `Accept-Encoding` header into codings and their weight, in
+/// thousandths.
+fn accepted_codings(headers: &HeaderMap) -> Vec<(String, u16)> {
+    headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|element| {
+            let mut params = element.split(';');
+            let coding = params.next()?.trim();
+            if coding.is_empty() {
+                return None;
+            }
+            let q = params
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .map_or(Some(1000), |(_, q)| parse_qvalue(q.trim()))?;
+            Some((coding.to_string(), q))
+        })
+        .collect()
+}
+
+fn parse_qvalue(q: &str) -> Option<u16> {
+    let q: f32 = q.parse().ok()?;
+    if (0.0..=1.0).contains(&q) {
+        Some((q * 1000.0).round() as u16)
+    } else {
+        None
+    }
+}
+
+fn qvalue(accepted: &[(String, u16)], coding: &str) -> Option<u16> {
+    accepted
+        .iter()
+        .find(|(c, _)| c.eq_ignore_ascii_case(coding))
+        .or_else(|| accepted.iter().find(|(c, _)| c == "*"))
+        .map(|(_, q)| *q)
+}
+
+fn qvalue_for(accepted: &[(String, u16)], encoding: Encoding) -> Option<u16> {
+    accepted
+        .iter()
+        .find(|(c, _)| encoding.matches(c))
+        .or_else(|| accepted.iter().find(|(c, _)| c == "*"))
+        .map(|(_, q)| *q)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accepted_codings, parse_qvalue};
+
+    use conduit::header::{self, HeaderMap, HeaderValue};
+
+    #[test]
+    fn parse_accept_encoding() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_static("gzip, br;q=0.8, identity; q=0, *;q=0.1, bad;q=2"),
+        );
+        assert_eq!(
+            accepted_codings(&headers),
+            vec![
+                ("gzip".to_string(), 1000),
+                ("br".to_string(), 800),
+                ("identity".to_string(), 0),
+                ("*".to_string(), 100),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_qvalues() {
+        assert_eq!(parse_qvalue("1"), Some(1000));
+        assert_eq!(parse_qvalue("0.5"), Some(500));
+        assert_eq!(parse_qvalue("0.001"), Some(1));
+        assert_eq!(parse_qvalue("1.5"), None);
+        assert_eq!(parse_qvalue("x"), None);
+    }
+}
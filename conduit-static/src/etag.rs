@@ -0,0 +1,125 @@
+//! Entity tag generation for static files.
+
+use std::collections::HashMap;
+use std::fs::{File, Metadata};
+use std::io::{self, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use conduit_conditional_get::read_content_hash;
+use filetime::FileTime;
+
+/// How `Static` derives the `ETag` of a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EtagStrategy {
+    /// Don't send an `ETag` header.
+    Disabled,
+    /// Derive the tag from the file's size, modification time (with
+    /// sub-second precision) and, on Unix, its inode number.
+    ///
+    /// This is cheap, but the tag changes whenever the file is touched or
+    /// replaced, even if its contents stay the same.
+    Metadata,
+    /// Hash the contents of the file.
+    ///
+    /// Hashes are cached per path and recomputed when the size or the
+    /// modification time of the file changes.
+    ContentHash,
+}
+
+impl Default for EtagStrategy {
+    fn default() -> EtagStrategy {
+        EtagStrategy::Metadata
+    }
+}
+
+struct CachedHash {
+    len: u64,
+    mtime: FileTime,
+    hash: String,
+}
+
+#[derive(Default)]
+pub(crate) struct EtagCache {
+    hashes: Mutex<HashMap<PathBuf, CachedHash>>,
+}
+
+impl EtagCache {
+    /// Compute the `ETag` header value of the file at `path`.
+    ///
+    /// The file is read when hashing its contents, but its position is
+    /// restored to the start before returning.
+    pub(crate) fn etag(
+        &self,
+        strategy: EtagStrategy,
+        weak: bool,
+        path: &Path,
+        mut file: &File,
+        data: &Metadata,
+    ) -> io::Result<Option<String>> {
+        let mtime = FileTime::from_last_modification_time(data);
+        let tag = match strategy {
+            EtagStrategy::Disabled => return Ok(None),
+            EtagStrategy::Metadata => {
+                let mut tag = format!(
+                    "{:x}-{:x}.{:x}",
+                    data.len(),
+                    mtime.unix_seconds(),
+                    mtime.nanoseconds()
+                );
+                if let Some(inode) = inode(data) {
+                    tag.push_str(&format!("-{:x}", inode));
+                }
+                tag
+            }
+            EtagStrategy::ContentHash => {
+                let cached = match self.hashes.lock().unwrap().get(path) {
+                    Some(cached) if cached.len == data.len() && cached.mtime == mtime => {
+                        Some(cached.hash.clone())
+                    }
+                    _ => None,
+                };
+                match cached {
+                    Some(hash) => hash,
+                    None => {
+                        // Hash without holding the lock, so that large files
+                        // don't block requests for other files
+                        let hash = read_content_hash(&mut file)?;
+                        file.seek(SeekFrom::Start(0))?;
+                        let cached = CachedHash {
+                            len: data.len(),
+                            mtime,
+                            hash: hash.clone(),
+                        };
+                        self.hashes
+                            .lock()
+                            .unwrap()
+                            .insert(path.to_path_buf(), cached);
+                        hash
+                    }
+                }
+            }
+        };
+
+        Ok(Some(format_etag(&tag, weak)))
+    }
+}
+
+pub(crate) fn format_etag(tag: &str, weak: bool) -> String {
+    if weak {
+        format!("W/\"{}\"", tag)
+    } else {
+        format!("\"{}\"", tag)
+    }
+}
+
+#[cfg(unix)]
+fn inode(data: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(data.ino())
+}
+
+#[cfg(not(unix))]
+fn inode(_: &Metadata) -> Option<u64> {
+    None
+}
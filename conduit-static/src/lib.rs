@@ -1,30 +1,176 @@
-use conduit::{box_error, header, Body, Handler, HandlerResult, RequestExt, Response, StatusCode};
+use conduit::{
+    box_error, header, Body, Handler, HandlerResult, HeaderMap, RequestExt, Response, StatusCode,
+};
+use conduit_httpdate::fmt_http_date;
 use conduit_mime_types as mime;
 use filetime::FileTime;
-use std::fs::File;
+use std::fs::{self, File};
 use std::path::{Path, PathBuf};
-use time::OffsetDateTime;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub use crate::cache::{CachePolicy, CacheRule};
+pub use crate::embedded::Embedded;
+pub use crate::encoding::Encoding;
+pub use crate::etag::EtagStrategy;
+pub use crate::listing::Autoindex;
+pub use crate::path::{Dotfiles, Symlinks};
+
+use crate::path::RequestPath;
+
+mod cache;
+mod embedded;
+mod encoding;
+mod etag;
+mod listing;
+mod path;
+mod range;
 
 pub struct Static {
     path: PathBuf,
+    etag: EtagStrategy,
+    weak_etags: bool,
+    etags: etag::EtagCache,
+    index_files: Vec<String>,
+    autoindex: Autoindex,
+    encodings: Vec<Encoding>,
+    symlinks: Symlinks,
+    canonical_root: Option<PathBuf>,
+    dotfiles: Dotfiles,
+    cache: Vec<(CacheRule, CachePolicy)>,
 }
 
 impl Static {
     pub fn new<P: AsRef<Path>>(path: P) -> Static {
+        let symlinks = Symlinks::default();
         Static {
             path: path.as_ref().to_path_buf(),
+            etag: EtagStrategy::default(),
+            weak_etags: false,
+            etags: Default::default(),
+            index_files: Vec::new(),
+            autoindex: Autoindex::default(),
+            encodings: Vec::new(),
+            symlinks,
+            canonical_root: path::canonical_root(symlinks, path.as_ref()),
+            dotfiles: Dotfiles::default(),
+            cache: Vec::new(),
         }
     }
 
+    /// Serve precompressed siblings, such as `app.js.gz` for `app.js`, to
+    /// clients accepting `encoding`.
+    ///
+    /// When several variants are equally acceptable to the client, the
+    /// encoding added first is preferred.
+    pub fn precompressed(&mut self, encoding: Encoding) -> &mut Static {
+        self.encodings.push(encoding);
+        self
+    }
+
+    /// Add a file name, such as `index.html`, to serve for requests of a
+    /// directory.
+    ///
+    /// Index files are tried in the order they were added.
+    pub fn index_file(&mut self, name: &str) -> &mut Static {
+        self.index_files.push(name.to_string());
+        self
+    }
+
+    /// Set how directories without an index file are served.
+    ///
+    /// Defaults to `Autoindex::Disabled`.
+    pub fn autoindex(&mut self, autoindex: Autoindex) -> &mut Static {
+        self.autoindex = autoindex;
+        self
+    }
+
+    /// Set how the `ETag` header of files is derived.
+    ///
+    /// Defaults to `EtagStrategy::Metadata`.
+    pub fn etag(&mut self, strategy: EtagStrategy) -> &mut Static {
+        self.etag = strategy;
+        self
+    }
+
+    /// Send weak (`W/"..."`) instead of strong entity tags.
+    ///
+    /// Weak tags still allow `If-None-Match` revalidation, but can't be used
+    /// to resume downloads with `If-Range`.
+    pub fn weak_etags(&mut self, weak: bool) -> &mut Static {
+        self.weak_etags = weak;
+        self
+    }
+
+    /// Send the caching headers of `policy` for files matching `rule`.
+    ///
+    /// Rules are tried in the order they were added and the first match
+    /// wins. Files not matching any rule are sent without caching headers.
+    pub fn cache(&mut self, rule: CacheRule, policy: CachePolicy) -> &mut Static {
+        self.cache.push((rule, policy));
+        self
+    }
+
+    /// Set how symbolic links below the root directory are followed.
+    ///
+    /// Defaults to `Symlinks::WithinRoot`.
+    pub fn symlinks(&mut self, symlinks: Symlinks) -> &mut Static {
+        self.symlinks = symlinks;
+        self.canonical_root = path::canonical_root(symlinks, &self.path);
+        self
+    }
+
+    /// Set whether hidden files and directories are served.
+    ///
+    /// Defaults to `Dotfiles::Allow`. Hidden entries are never included in
+    /// directory listings.
+    pub fn dotfiles(&mut self, dotfiles: Dotfiles) -> &mut Static {
+        self.dotfiles = dotfiles;
+        self
+    }
+
+    /// Look up the file or directory at `request_path`.
+    ///
+    /// The path is percent-decoded and normalized before it is resolved
+    /// against the root directory. Paths leaving the root respond with
+    /// `404 Not Found`.
+    ///
+    /// Directories are served using their index file or generated listing,
+    /// if any. Requests for such a directory without a trailing slash are
+    /// redirected, so that relative links in the page resolve correctly.
     pub fn lookup(&self, request_path: &str) -> HandlerResult {
-        let request_path = request_path.strip_prefix('/').unwrap_or(request_path);
-        if request_path.contains("..") {
-            return Ok(not_found());
+        lookup_path(self, request_path, None, &HeaderMap::new())
+    }
+
+    /// The caching policy of the file at `path`, below the root directory.
+    fn cache_policy(&self, path: &Path) -> Option<&CachePolicy> {
+        if self.cache.is_empty() {
+            return None;
         }
+        let relative = path.strip_prefix(&self.path).ok()?;
+        let relative = relative
+            .iter()
+            .map(|c| c.to_str())
+            .collect::<Option<Vec<_>>>()?
+            .join("/");
+        cache::policy_for(&self.cache, &relative)
+    }
 
-        let path = self.path.join(request_path);
-        let mime = mime::mime_for_path(&path).unwrap_or("application/octet-stream");
-        let file = match File::open(&path) {
+    /// Whether `path` may be served under the symlink policy.
+    fn is_allowed(&self, path: &Path) -> bool {
+        let canonical_root = self.canonical_root.as_deref();
+        path::symlinks_allowed(self.symlinks, &self.path, canonical_root, path).unwrap_or(false)
+    }
+
+    fn lookup_file(&self, path: &Path, headers: &HeaderMap) -> HandlerResult {
+        // The type is that of the original file, even for compressed variants
+        let mime = mime::mime_for_path(path).unwrap_or("application/octet-stream");
+        let variant = encoding::negotiate(path, &self.encodings, headers, Path::is_file);
+        let original = path;
+        let path = &variant.path;
+        if !self.is_allowed(path) {
+            return Ok(not_found());
+        }
+        let file = match File::open(path) {
             Ok(f) => f,
             Err(..) => return Ok(not_found()),
         };
@@ -32,22 +178,198 @@ impl Static {
         if data.is_dir() {
             return Ok(not_found());
         }
-        let mtime = FileTime::from_last_modification_time(&data);
-        let mtime = OffsetDateTime::from_unix_timestamp(mtime.unix_seconds());
+        let etag = self
+            .etags
+            .etag(self.etag, self.weak_etags, path, &file, &data)
+            .map_err(box_error)?;
 
-        Response::builder()
-            .header(header::CONTENT_TYPE, mime)
-            .header(header::CONTENT_LENGTH, data.len())
-            .header(header::LAST_MODIFIED, mtime.format("%a, %d %b %Y %T GMT"))
-            .body(Body::File(file))
-            .map_err(box_error)
+        FileHeaders {
+            mime,
+            len: data.len(),
+            last_modified: Some(http_date(FileTime::from_last_modification_time(&data))),
+            etag,
+            variant: &variant,
+            cache: self.cache_policy(original),
+        }
+        .respond(Body::File(file))
+    }
+}
+
+impl Files for Static {
+    type Key = PathBuf;
+
+    fn find(&self, path: &RequestPath) -> Option<Entry<PathBuf>> {
+        if self.dotfiles == Dotfiles::Deny && path.is_hidden() {
+            return None;
+        }
+        let path = path.join_to(&self.path);
+        match fs::metadata(&path) {
+            Ok(data) if data.is_dir() && self.is_allowed(&path) => Some(Entry::Dir(path)),
+            Ok(data) if data.is_dir() => None,
+            Ok(_) => Some(Entry::File(path)),
+            Err(..) => None,
+        }
+    }
+
+    fn index(&self, dir: &PathBuf) -> Option<PathBuf> {
+        self.index_files
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+    }
+
+    fn respond(&self, path: &PathBuf, headers: &HeaderMap) -> HandlerResult {
+        self.lookup_file(path, headers)
+    }
+
+    fn lists_directories(&self) -> bool {
+        self.autoindex != Autoindex::Disabled
+    }
+
+    fn list(&self, path: &RequestPath, dir: &PathBuf) -> HandlerResult {
+        listing::respond(self.autoindex, &path.display(), dir, |path| {
+            self.is_allowed(path)
+        })
     }
 }
 
 impl Handler for Static {
     fn call(&self, request: &mut dyn RequestExt) -> HandlerResult {
-        self.lookup(request.path())
+        let headers = request.headers();
+        let response = lookup_path(self, request.path(), request.query_string(), headers)?;
+        range::respond(request, response)
+    }
+}
+
+/// A file or directory found by `Files::find`.
+enum Entry<K> {
+    File(K),
+    Dir(K),
+}
+
+/// The files served by `Static` and `Embedded`, for `lookup_path`.
+trait Files {
+    /// Identifies a file or directory, such as its path.
+    type Key;
+
+    /// The file or directory at `path`, if it may be served.
+    fn find(&self, path: &RequestPath) -> Option<Entry<Self::Key>>;
+
+    /// The index file of `dir`, if any.
+    fn index(&self, dir: &Self::Key) -> Option<Self::Key>;
+
+    /// Respond with the contents of `file`.
+    fn respond(&self, file: &Self::Key, headers: &HeaderMap) -> HandlerResult;
+
+    /// Whether directories without an index file are served with `list`.
+    fn lists_directories(&self) -> bool {
+        false
+    }
+
+    /// Respond with a listing of `dir`, requested at `path`.
+    fn list(&self, _path: &RequestPath, _dir: &Self::Key) -> HandlerResult {
+        Ok(not_found())
+    }
+}
+
+/// Look up the file or directory at `request_path`, see `Static::lookup`.
+fn lookup_path<F: Files>(
+    files: &F,
+    request_path: &str,
+    query: Option<&str>,
+    headers: &HeaderMap,
+) -> HandlerResult {
+    let path = match RequestPath::parse(request_path) {
+        Some(path) => path,
+        None => return Ok(not_found()),
+    };
+    let dir = match files.find(&path) {
+        Some(Entry::File(file)) if !path.has_trailing_slash() => {
+            return files.respond(&file, headers)
+        }
+        Some(Entry::Dir(dir)) => dir,
+        Some(Entry::File(_)) | None => return Ok(not_found()),
+    };
+
+    let index = files.index(&dir);
+    if index.is_none() && !files.lists_directories() {
+        return Ok(not_found());
+    }
+    if !path.is_root() && !path.has_trailing_slash() {
+        return Ok(redirect_to_dir(request_path, query));
+    }
+    match index {
+        Some(index) => files.respond(&index, headers),
+        None => files.list(&path, &dir),
+    }
+}
+
+/// The headers of a successful response for a file, shared by `Static` and
+/// `Embedded`.
+struct FileHeaders<'a> {
+    mime: &'a str,
+    len: u64,
+    last_modified: Option<String>,
+    etag: Option<String>,
+    variant: &'a encoding::Variant,
+    cache: Option<&'a CachePolicy>,
+}
+
+impl FileHeaders<'_> {
+    fn respond(self, body: Body) -> HandlerResult {
+        let mut builder = Response::builder()
+            .header(header::CONTENT_TYPE, self.mime)
+            .header(header::CONTENT_LENGTH, self.len)
+            .header(header::ACCEPT_RANGES, "bytes");
+        if let Some(last_modified) = self.last_modified {
+            builder = builder.header(header::LAST_MODIFIED, last_modified);
+        }
+        if let Some(etag) = self.etag {
+            builder = builder.header(header::ETAG, etag);
+        }
+        if let Some(encoding) = self.variant.encoding {
+            builder = builder.header(header::CONTENT_ENCODING, encoding.name());
+        }
+        if self.variant.varies {
+            builder = builder.header(header::VARY, "Accept-Encoding");
+        }
+        if let Some(policy) = self.cache {
+            builder = builder.header(header::CACHE_CONTROL, policy.cache_control());
+            if let Some(expires) = policy.expires_at(SystemTime::now()) {
+                builder = builder.header(header::EXPIRES, fmt_http_date(expires));
+            }
+        }
+        builder.body(body).map_err(box_error)
+    }
+}
+
+/// The modification time of a file as an HTTP date.
+fn http_date(time: FileTime) -> String {
+    let secs = Duration::from_secs(time.unix_seconds().unsigned_abs());
+    if time.unix_seconds() >= 0 {
+        fmt_http_date(UNIX_EPOCH + secs)
+    } else {
+        fmt_http_date(UNIX_EPOCH - secs)
+    }
+}
+
+/// Redirect a request for a directory to the same path with a trailing
+/// slash, keeping the query string.
+fn redirect_to_dir(request_path: &str, query: Option<&str>) -> Response<Body> {
+    // Redirect relative to the current URL, which is correct regardless of
+    // where this handler is mounted.
+    let mut location = request_path.rsplit('/').next().unwrap_or("").to_string();
+    location.push('/');
+    if let Some(query) = query {
+        location.push('?');
+        location.push_str(query);
     }
+    Response::builder()
+        .status(StatusCode::MOVED_PERMANENTLY)
+        .header(header::LOCATION, location)
+        .header(header::CONTENT_LENGTH, 0)
+        .body(Body::empty())
+        .unwrap()
 }
 
 fn not_found() -> Response<Body> {
@@ -63,10 +385,15 @@ fn not_found() -> Response<Body> {
 mod tests {
     use std::fs::{self, File};
     use std::io::prelude::*;
+    use std::time::Duration;
     use tempdir::TempDir;
 
-    use crate::Static;
-    use conduit::{header, Handler, Method, StatusCode};
+    use crate::{
+        Autoindex, CachePolicy, CacheRule, Dotfiles, Encoding, EtagStrategy, Static, Symlinks,
+    };
+    use conduit::{header, Body, Handler, Method, Response, StatusCode};
+    use conduit_conditional_get::ConditionalGet;
+    use conduit_middleware::MiddlewareBuilder;
     use conduit_test::{MockRequest, ResponseExt};
 
     #[test]
@@ -74,7 +401,7 @@ mod tests {
         let td = TempDir::new("conduit-static").unwrap();
         let root = td.path();
         let handler = Static::new(root);
-        File::create(&root.join("Cargo.toml"))
+        File::create(root.join("Cargo.toml"))
             .unwrap()
             .write_all(b"[package]")
             .unwrap();
@@ -93,7 +420,7 @@ mod tests {
         let td = TempDir::new("conduit-static").unwrap();
         let root = td.path();
         let handler = Static::new(root);
-        File::create(&root.join("Cargo.toml"))
+        File::create(root.join("Cargo.toml"))
             .unwrap()
             .write_all(b"[package]")
             .unwrap();
@@ -111,8 +438,8 @@ mod tests {
     fn test_mime_types() {
         let td = TempDir::new("conduit-static").unwrap();
         let root = td.path();
-        fs::create_dir(&root.join("src")).unwrap();
-        File::create(&root.join("src/fixture.css")).unwrap();
+        fs::create_dir(root.join("src")).unwrap();
+        File::create(root.join("src/fixture.css")).unwrap();
 
         let handler = Static::new(root);
         let mut req = MockRequest::new(Method::GET, "/src/fixture.css");
@@ -137,7 +464,7 @@ mod tests {
         let td = TempDir::new("conduit-static").unwrap();
         let root = td.path();
 
-        fs::create_dir(&root.join("foo")).unwrap();
+        fs::create_dir(root.join("foo")).unwrap();
 
         let handler = Static::new(root);
         let mut req = MockRequest::new(Method::GET, "/foo");
@@ -145,11 +472,210 @@ mod tests {
         assert_eq!(res.status(), StatusCode::NOT_FOUND);
     }
 
+    #[test]
+    fn index_file() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        fs::create_dir(root.join("docs")).unwrap();
+        File::create(root.join("docs/index.html"))
+            .unwrap()
+            .write_all(b"<h1>docs</h1>")
+            .unwrap();
+        File::create(root.join("index.htm")).unwrap();
+        let mut handler = Static::new(root);
+        handler.index_file("index.html").index_file("index.htm");
+
+        let mut req = MockRequest::new(Method::GET, "/docs/");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/html");
+        assert_eq!(*res.into_cow(), b"<h1>docs</h1>"[..]);
+
+        let mut req = MockRequest::new(Method::GET, "/");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn directory_redirect() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        fs::create_dir_all(root.join("docs/api")).unwrap();
+        File::create(root.join("docs/api/index.html")).unwrap();
+        let mut handler = Static::new(root);
+        handler.index_file("index.html");
+
+        let mut req = MockRequest::new(Method::GET, "/docs/api");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(res.headers()[header::LOCATION], "api/");
+
+        let mut req = MockRequest::new(Method::GET, "/docs/api");
+        req.with_query("page=2");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.headers()[header::LOCATION], "api/?page=2");
+
+        // Directories that can't be served aren't redirected
+        let mut req = MockRequest::new(Method::GET, "/docs");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn autoindex_html() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        fs::create_dir(root.join("sub dir")).unwrap();
+        File::create(root.join("<b>.txt"))
+            .unwrap()
+            .write_all(b"12345")
+            .unwrap();
+        File::create(root.join(".hidden")).unwrap();
+        let mut handler = Static::new(root);
+        handler.autoindex(Autoindex::Html);
+
+        let mut req = MockRequest::new(Method::GET, "/");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+        let body = String::from_utf8(res.into_cow().into_owned()).unwrap();
+        assert!(body.contains("<title>Index of /</title>"), "{}", body);
+        assert!(
+            body.contains("<a href=\"%3Cb%3E.txt\">&lt;b&gt;.txt</a></td><td>5</td>"),
+            "{}",
+            body
+        );
+        assert!(
+            body.contains("<a href=\"sub%20dir/\">sub dir/</a>"),
+            "{}",
+            body
+        );
+        assert!(!body.contains(".hidden"), "{}", body);
+    }
+
+    #[test]
+    fn autoindex_json() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        fs::create_dir_all(root.join("dir/sub")).unwrap();
+        File::create(root.join("dir/file.txt"))
+            .unwrap()
+            .write_all(b"123")
+            .unwrap();
+        let mut handler = Static::new(root);
+        handler.autoindex(Autoindex::Json);
+
+        let mut req = MockRequest::new(Method::GET, "/dir");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+
+        let mut req = MockRequest::new(Method::GET, "/dir/");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+        let body: serde_json::Value = serde_json::from_slice(&res.into_cow()).unwrap();
+        let entries = body.as_array().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["name"], "file.txt");
+        assert_eq!(entries[0]["type"], "file");
+        assert_eq!(entries[0]["size"], 3);
+        assert!(entries[0]["modified"].as_str().unwrap().ends_with(" GMT"));
+        assert_eq!(entries[1]["name"], "sub");
+        assert_eq!(entries[1]["type"], "directory");
+    }
+
+    fn precompressed_root() -> TempDir {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        File::create(root.join("app.js"))
+            .unwrap()
+            .write_all(b"raw")
+            .unwrap();
+        File::create(root.join("app.js.gz"))
+            .unwrap()
+            .write_all(b"gzipped")
+            .unwrap();
+        File::create(root.join("app.js.br"))
+            .unwrap()
+            .write_all(b"brotli")
+            .unwrap();
+        File::create(root.join("plain.css")).unwrap();
+        td
+    }
+
+    fn get_encoded(handler: &Static, path: &str, accept: Option<&str>) -> Response<Body> {
+        let mut req = MockRequest::new(Method::GET, path);
+        if let Some(accept) = accept {
+            req.header(header::ACCEPT_ENCODING, accept);
+        }
+        handler.call(&mut req).expect("No response")
+    }
+
+    #[test]
+    fn precompressed_variants() {
+        let td = precompressed_root();
+        let mut handler = Static::new(td.path());
+        handler
+            .precompressed(Encoding::Brotli)
+            .precompressed(Encoding::Gzip);
+
+        let res = get_encoded(&handler, "/app.js", Some("gzip, deflate, br"));
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "br");
+        assert_eq!(
+            res.headers()[header::CONTENT_TYPE],
+            "application/javascript"
+        );
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "6");
+        assert_eq!(res.headers()[header::VARY], "Accept-Encoding");
+        let brotli_etag = res.headers()[header::ETAG].clone();
+        assert_eq!(*res.into_cow(), b"brotli"[..]);
+
+        let res = get_encoded(&handler, "/app.js", Some("gzip, br;q=0.5"));
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_ne!(res.headers()[header::ETAG], brotli_etag);
+        assert_eq!(*res.into_cow(), b"gzipped"[..]);
+
+        let res = get_encoded(&handler, "/app.js", Some("*;q=0.5, identity"));
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(res.headers()[header::VARY], "Accept-Encoding");
+        assert_eq!(*res.into_cow(), b"raw"[..]);
+
+        let res = get_encoded(&handler, "/app.js", Some("br;q=0, gzip;q=0"));
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+
+        let res = get_encoded(&handler, "/app.js", None);
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(res.headers()[header::VARY], "Accept-Encoding");
+    }
+
+    #[test]
+    fn precompressed_missing_variant() {
+        let td = precompressed_root();
+        let mut handler = Static::new(td.path());
+        handler.precompressed(Encoding::Gzip);
+
+        let res = get_encoded(&handler, "/plain.css", Some("gzip"));
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+        assert!(res.headers().get(header::VARY).is_none());
+    }
+
+    #[test]
+    fn precompressed_disabled() {
+        let td = precompressed_root();
+        let handler = Static::new(td.path());
+
+        let res = get_encoded(&handler, "/app.js", Some("gzip, br"));
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(*res.into_cow(), b"raw"[..]);
+    }
+
     #[test]
     fn last_modified() {
         let td = TempDir::new("conduit-static").unwrap();
         let root = td.path();
-        File::create(&root.join("test")).unwrap();
+        File::create(root.join("test")).unwrap();
         let handler = Static::new(root);
         let mut req = MockRequest::new(Method::GET, "/test");
         let res = handler.call(&mut req).expect("No response");
@@ -157,14 +683,409 @@ mod tests {
         assert!(res.headers().get(header::LAST_MODIFIED).is_some());
     }
 
+    #[test]
+    fn accept_ranges() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        File::create(root.join("test")).unwrap();
+        let handler = Static::new(root);
+        let res = handler.lookup("test").expect("No response");
+        assert_eq!(res.headers().get(header::ACCEPT_RANGES).unwrap(), "bytes");
+    }
+
+    #[test]
+    fn single_range() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        File::create(root.join("test.txt"))
+            .unwrap()
+            .write_all(b"0123456789")
+            .unwrap();
+        let handler = Static::new(root);
+        let mut req = MockRequest::new(Method::GET, "/test.txt");
+        req.header(header::RANGE, "bytes=2-5");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            res.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes 2-5/10"
+        );
+        assert_eq!(res.headers().get(header::CONTENT_LENGTH).unwrap(), "4");
+        assert_eq!(
+            res.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/plain"
+        );
+        assert_eq!(*res.into_cow(), b"2345"[..]);
+
+        let mut req = MockRequest::new(Method::GET, "/test.txt");
+        req.header(header::RANGE, "bytes=-3");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(
+            res.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes 7-9/10"
+        );
+        assert_eq!(*res.into_cow(), b"789"[..]);
+    }
+
+    #[test]
+    fn multiple_ranges() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        File::create(root.join("test.txt"))
+            .unwrap()
+            .write_all(b"0123456789")
+            .unwrap();
+        let handler = Static::new(root);
+        let mut req = MockRequest::new(Method::GET, "/test.txt");
+        req.header(header::RANGE, "bytes=0-1, 8-");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+
+        let content_type = res.headers().get(header::CONTENT_TYPE).unwrap();
+        let content_type = content_type.to_str().unwrap().to_string();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+        let length: usize = res.headers()[header::CONTENT_LENGTH]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        let expected = format!(
+            "\r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-1/10\r\n\r\n01\
+             \r\n--{b}\r\nContent-Type: text/plain\r\nContent-Range: bytes 8-9/10\r\n\r\n89\
+             \r\n--{b}--\r\n",
+            b = boundary
+        );
+        let body = res.into_cow();
+        assert_eq!(body.len(), length);
+        assert_eq!(std::str::from_utf8(&body).unwrap(), expected);
+    }
+
+    #[test]
+    fn range_not_satisfiable() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        File::create(root.join("test.txt"))
+            .unwrap()
+            .write_all(b"0123456789")
+            .unwrap();
+        let handler = Static::new(root);
+        let mut req = MockRequest::new(Method::GET, "/test.txt");
+        req.header(header::RANGE, "bytes=10-20");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(
+            res.headers().get(header::CONTENT_RANGE).unwrap(),
+            "bytes */10"
+        );
+    }
+
+    #[test]
+    fn invalid_range_is_ignored() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        File::create(root.join("test.txt"))
+            .unwrap()
+            .write_all(b"0123456789")
+            .unwrap();
+        let handler = Static::new(root);
+        for range in ["bytes=5-2", "bytes="] {
+            let mut req = MockRequest::new(Method::GET, "/test.txt");
+            req.header(header::RANGE, range);
+            let res = handler.call(&mut req).expect("No response");
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(*res.into_cow(), b"0123456789"[..]);
+        }
+    }
+
+    #[test]
+    fn if_range() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        File::create(root.join("test.txt"))
+            .unwrap()
+            .write_all(b"0123456789")
+            .unwrap();
+        let handler = Static::new(root);
+        let last_modified = handler.lookup("test.txt").unwrap().headers()[header::LAST_MODIFIED]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let mut req = MockRequest::new(Method::GET, "/test.txt");
+        req.header(header::RANGE, "bytes=0-0");
+        req.header(header::IF_RANGE, &last_modified);
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+
+        let mut req = MockRequest::new(Method::GET, "/test.txt");
+        req.header(header::RANGE, "bytes=0-0");
+        req.header(header::IF_RANGE, "Thu, 01 Jan 1970 00:00:00 GMT");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(*res.into_cow(), b"0123456789"[..]);
+
+        let mut req = MockRequest::new(Method::GET, "/test.txt");
+        req.header(header::RANGE, "bytes=0-0");
+        req.header(header::IF_RANGE, "\"nope\"");
+        let res = handler.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn etag_from_metadata() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        File::create(root.join("test")).unwrap();
+        let handler = Static::new(root);
+        let etag = |handler: &Static| {
+            let res = handler.lookup("test").expect("No response");
+            res.headers()[header::ETAG].to_str().unwrap().to_string()
+        };
+
+        let first = etag(&handler);
+        assert!(first.starts_with('"') && first.ends_with('"'), "{}", first);
+        assert_eq!(etag(&handler), first);
+
+        File::create(root.join("test"))
+            .unwrap()
+            .write_all(b"changed")
+            .unwrap();
+        assert_ne!(etag(&handler), first);
+    }
+
+    #[test]
+    fn etag_from_content_hash() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        File::create(root.join("a"))
+            .unwrap()
+            .write_all(b"same")
+            .unwrap();
+        File::create(root.join("b"))
+            .unwrap()
+            .write_all(b"same")
+            .unwrap();
+        File::create(root.join("c"))
+            .unwrap()
+            .write_all(b"diff")
+            .unwrap();
+        let mut handler = Static::new(root);
+        handler.etag(EtagStrategy::ContentHash);
+        let etag = |path: &str| {
+            let res = handler.lookup(path).expect("No response");
+            res.headers()[header::ETAG].to_str().unwrap().to_string()
+        };
+
+        assert_eq!(etag("a"), etag("b"));
+        assert_eq!(etag("a"), etag("a"));
+        assert_ne!(etag("a"), etag("c"));
+
+        // Hashing must not consume the body
+        let res = handler.lookup("a").expect("No response");
+        assert_eq!(*res.into_cow(), b"same"[..]);
+    }
+
+    #[test]
+    fn weak_and_disabled_etags() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        File::create(root.join("test")).unwrap();
+        let mut handler = Static::new(root);
+
+        handler.weak_etags(true);
+        let res = handler.lookup("test").expect("No response");
+        assert!(res.headers()[header::ETAG].as_bytes().starts_with(b"W/\""));
+
+        handler.etag(EtagStrategy::Disabled);
+        let res = handler.lookup("test").expect("No response");
+        assert!(res.headers().get(header::ETAG).is_none());
+    }
+
+    #[test]
+    fn conditional_get_with_etag() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        File::create(root.join("test")).unwrap();
+        let etag = Static::new(root).lookup("test").unwrap().headers()[header::ETAG].clone();
+
+        let mut stack = MiddlewareBuilder::new(Static::new(root));
+        stack.add(ConditionalGet);
+        let mut req = MockRequest::new(Method::GET, "/test");
+        req.header(header::IF_NONE_MATCH, etag.to_str().unwrap());
+        let res = stack.call(&mut req).expect("No response");
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+
     #[test]
     fn emoji_path() {
         let td = TempDir::new("conduit-static").unwrap();
         let root = td.path();
-        File::create(&root.join("test")).unwrap();
+        File::create(root.join("test")).unwrap();
         let handler = Static::new(root);
         let mut req = MockRequest::new(Method::GET, "🎉");
         let res = handler.call(&mut req).expect("No response");
         assert_eq!(res.status(), StatusCode::NOT_FOUND);
     }
+
+    fn status(handler: &Static, path: &str) -> StatusCode {
+        let mut req = MockRequest::new(Method::GET, path);
+        handler.call(&mut req).expect("No response").status()
+    }
+
+    #[test]
+    fn traversal_is_rejected() {
+        let td = TempDir::new("conduit-static").unwrap();
+        File::create(td.path().join("secret")).unwrap();
+        let root = td.path().join("public");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        File::create(root.join("file")).unwrap();
+        let handler = Static::new(&root);
+
+        assert_eq!(status(&handler, "/../secret"), StatusCode::NOT_FOUND);
+        assert_eq!(status(&handler, "/sub/../../secret"), StatusCode::NOT_FOUND);
+        assert_eq!(status(&handler, "/%2e%2e/secret"), StatusCode::NOT_FOUND);
+        assert_eq!(status(&handler, "/%2E%2E/secret"), StatusCode::NOT_FOUND);
+        assert_eq!(
+            status(&handler, "/sub/..%2f..%2fsecret"),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(status(&handler, "/..%5csecret"), StatusCode::NOT_FOUND);
+        assert_eq!(status(&handler, "/file%00.txt"), StatusCode::NOT_FOUND);
+        assert_eq!(status(&handler, "/sub/%2e%2e/file"), StatusCode::OK);
+        assert_eq!(status(&handler, "/./sub/../file"), StatusCode::OK);
+        assert_eq!(status(&handler, "//file"), StatusCode::OK);
+    }
+
+    #[test]
+    fn percent_encoded_names() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        File::create(root.join("foo..bar.txt")).unwrap();
+        File::create(root.join("a b.txt")).unwrap();
+        File::create(root.join("🎉.txt")).unwrap();
+        let handler = Static::new(root);
+
+        assert_eq!(status(&handler, "/foo..bar.txt"), StatusCode::OK);
+        assert_eq!(status(&handler, "/a%20b.txt"), StatusCode::OK);
+        assert_eq!(status(&handler, "/%F0%9F%8E%89.txt"), StatusCode::OK);
+    }
+
+    #[test]
+    fn dotfiles() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        fs::create_dir(root.join(".git")).unwrap();
+        File::create(root.join(".git/config")).unwrap();
+        File::create(root.join(".env")).unwrap();
+        let mut handler = Static::new(root);
+
+        assert_eq!(status(&handler, "/.env"), StatusCode::OK);
+        handler.dotfiles(Dotfiles::Deny);
+        assert_eq!(status(&handler, "/.env"), StatusCode::NOT_FOUND);
+        assert_eq!(status(&handler, "/%2eenv"), StatusCode::NOT_FOUND);
+        assert_eq!(status(&handler, "/.git/config"), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let td = TempDir::new("conduit-static").unwrap();
+        File::create(td.path().join("secret")).unwrap();
+        let root = td.path().join("public");
+        fs::create_dir_all(root.join("dir")).unwrap();
+        File::create(root.join("dir/file")).unwrap();
+        symlink(td.path().join("secret"), root.join("outside")).unwrap();
+        symlink(td.path(), root.join("parent")).unwrap();
+        symlink(root.join("dir/file"), root.join("inside")).unwrap();
+        symlink(root.join("dir"), root.join("linked-dir")).unwrap();
+        let mut handler = Static::new(&root);
+
+        assert_eq!(status(&handler, "/outside"), StatusCode::NOT_FOUND);
+        assert_eq!(status(&handler, "/parent/secret"), StatusCode::NOT_FOUND);
+        assert_eq!(status(&handler, "/inside"), StatusCode::OK);
+        assert_eq!(status(&handler, "/linked-dir/file"), StatusCode::OK);
+
+        // Listings only show what can be served
+        handler.autoindex(Autoindex::Json);
+        let names = |handler: &Static| {
+            let mut req = MockRequest::new(Method::GET, "/");
+            let res = handler.call(&mut req).expect("No response");
+            let body: serde_json::Value = serde_json::from_slice(&res.into_cow()).unwrap();
+            body.as_array()
+                .unwrap()
+                .iter()
+                .map(|entry| entry["name"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&handler), ["dir", "inside", "linked-dir"]);
+
+        handler.symlinks(Symlinks::Deny);
+        assert_eq!(names(&handler), ["dir"]);
+        assert_eq!(status(&handler, "/inside"), StatusCode::NOT_FOUND);
+        assert_eq!(status(&handler, "/linked-dir/file"), StatusCode::NOT_FOUND);
+        assert_eq!(status(&handler, "/dir/file"), StatusCode::OK);
+
+        handler.symlinks(Symlinks::Follow);
+        assert_eq!(status(&handler, "/outside"), StatusCode::OK);
+        assert_eq!(status(&handler, "/parent/secret"), StatusCode::OK);
+    }
+
+    #[test]
+    fn cache_policies() {
+        let td = TempDir::new("conduit-static").unwrap();
+        let root = td.path();
+        fs::create_dir(root.join("assets")).unwrap();
+        File::create(root.join("assets/app.3f2a1b9c.js")).unwrap();
+        File::create(root.join("assets/app.js")).unwrap();
+        File::create(root.join("assets/site.css")).unwrap();
+        File::create(root.join("index.html")).unwrap();
+        File::create(root.join("robots.txt")).unwrap();
+        let mut handler = Static::new(root);
+        handler
+            .index_file("index.html")
+            .cache(CacheRule::fingerprinted(), CachePolicy::immutable())
+            .cache(
+                CacheRule::glob("assets/**").unwrap(),
+                CachePolicy::new("public, max-age=3600")
+                    .unwrap()
+                    .expires(Duration::from_secs(3600)),
+            )
+            .cache(CacheRule::extension("html"), CachePolicy::no_cache());
+
+        let headers = |path: &str| {
+            let mut req = MockRequest::new(Method::GET, path);
+            let res = handler.call(&mut req).expect("No response");
+            assert_eq!(res.status(), StatusCode::OK);
+            let get = |name| {
+                res.headers()
+                    .get(name)
+                    .map(|v| v.to_str().unwrap().to_string())
+            };
+            (get(header::CACHE_CONTROL), get(header::EXPIRES))
+        };
+
+        let (cache_control, expires) = headers("/assets/app.3f2a1b9c.js");
+        assert_eq!(
+            cache_control.as_deref(),
+            Some("public, max-age=31536000, immutable")
+        );
+        assert_eq!(expires, None);
+
+        let (cache_control, expires) = headers("/assets/app.js");
+        assert_eq!(cache_control.as_deref(), Some("public, max-age=3600"));
+        assert!(expires.unwrap().ends_with(" GMT"));
+
+        assert_eq!(
+            headers("/assets/site.css").0.as_deref(),
+            Some("public, max-age=3600")
+        );
+        assert_eq!(headers("/index.html").0.as_deref(), Some("no-cache"));
+        // The rules apply to the index file served for a directory
+        assert_eq!(headers("/").0.as_deref(), Some("no-cache"));
+        assert_eq!(headers("/robots.txt"), (None, None));
+    }
 }
@@ -0,0 +1,163 @@
+//! Generated listings of directory contents.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use conduit::{box_error, header, Body, HandlerResult, Response};
+use filetime::FileTime;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde_json::json;
+
+use crate::http_date;
+
+/// Characters that must be escaped in a relative link to a directory entry.
+const LINK: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b':')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'\\')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// How `Static` responds to requests for a directory without an index file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Autoindex {
+    /// Respond with `404 Not Found`.
+    Disabled,
+    /// Respond with an HTML page linking to the directory entries.
+    Html,
+    /// Respond with a JSON array describing the directory entries.
+    Json,
+}
+
+impl Default for Autoindex {
+    fn default() -> Autoindex {
+        Autoindex::Disabled
+    }
+}
+
+struct Entry {
+    name: String,
+    is_dir: bool,
+    size: u64,
+    modified: String,
+}
+
+fn entries(dir: &Path, allowed: impl Fn(&Path) -> bool) -> io::Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            // Names that aren't valid UTF-8 can't be linked to
+            Err(_) => continue,
+        };
+        // Don't list what wouldn't be served, such as symlinks leaving the
+        // root
+        if name.starts_with('.') || !allowed(&entry.path()) {
+            continue;
+        }
+        // Follow symlinks, so that they are listed like their targets
+        let data = match fs::metadata(entry.path()) {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+        entries.push(Entry {
+            name,
+            is_dir: data.is_dir(),
+            size: if data.is_dir() { 0 } else { data.len() },
+            modified: http_date(FileTime::from_last_modification_time(&data)),
+        });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+/// Respond with a listing of `dir`, which was requested as `request_path`,
+/// with the entries that are `allowed` to be served.
+pub(crate) fn respond(
+    kind: Autoindex,
+    request_path: &str,
+    dir: &Path,
+    allowed: impl Fn(&Path) -> bool,
+) -> HandlerResult {
+    let entries = entries(dir, allowed).map_err(box_error)?;
+    let (content_type, body) = match kind {
+        Autoindex::Disabled => unreachable!("listing requested with autoindex disabled"),
+        Autoindex::Html => ("text/html; charset=utf-8", html(request_path, &entries)),
+        Autoindex::Json => ("application/json", json(&entries)),
+    };
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, body.len())
+        .body(Body::from_vec(body))
+        .map_err(box_error)
+}
+
+fn html(request_path: &str, entries: &[Entry]) -> Vec<u8> {
+    let title = escape(request_path);
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Index of {title}</title>\n</head>\n<body>\n<h1>Index of {title}</h1>\n\
+         <table>\n<tr><th>Name</th><th>Size</th><th>Last modified</th></tr>\n\
+         <tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n",
+        title = title
+    );
+    for entry in entries {
+        let suffix = if entry.is_dir { "/" } else { "" };
+        let size = if entry.is_dir {
+            String::from("-")
+        } else {
+            entry.size.to_string()
+        };
+        page.push_str(&format!(
+            "<tr><td><a href=\"{href}{suffix}\">{name}{suffix}</a></td><td>{size}</td><td>{modified}</td></tr>\n",
+            href = utf8_percent_encode(&entry.name, LINK),
+            name = escape(&entry.name),
+            suffix = suffix,
+            size = size,
+            modified = entry.modified,
+        ));
+    }
+    page.push_str("</table>\n</body>\n</html>\n");
+    page.into_bytes()
+}
+
+fn json(entries: &[Entry]) -> Vec<u8> {
+    let entries: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "name": entry.name,
+                "type": if entry.is_dir { "directory" } else { "file" },
+                "size": entry.size,
+                "modified": entry.modified,
+            })
+        })
+        .collect();
+    serde_json::to_vec(&entries).unwrap()
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,235 @@
+//! Resolution of request paths to files below the root directory.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use percent_encoding::percent_decode_str;
+
+/// How `Static` treats symbolic links below its root directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Symlinks {
+    /// Follow all symbolic links, wherever they point to.
+    Follow,
+    /// Follow symbolic links as long as the file they resolve to is inside
+    /// the root directory.
+    WithinRoot,
+    /// Don't serve any path that goes through a symbolic link.
+    Deny,
+}
+
+impl Default for Symlinks {
+    fn default() -> Symlinks {
+        Symlinks::WithinRoot
+    }
+}
+
+/// How `Static` treats hidden files and directories, whose name starts with
+/// a dot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dotfiles {
+    /// Serve hidden files like any other file.
+    Allow,
+    /// Respond with `404 Not Found` for any path with a hidden component.
+    Deny,
+}
+
+impl Default for Dotfiles {
+    fn default() -> Dotfiles {
+        Dotfiles::Allow
+    }
+}
+
+/// A request path, decoded and normalized into components that are safe to
+/// join to the root directory.
+#[derive(Debug, PartialEq)]
+pub(crate) struct RequestPath {
+    segments: Vec<String>,
+    trailing_slash: bool,
+}
+
+impl RequestPath {
+    /// Decode and normalize the path of a request.
+    ///
+    /// Each segment is percent-decoded on its own, so that an encoded `/`
+    /// can't introduce new components. `.` and empty segments are skipped and
+    /// `..` removes the previous segment. Returns `None` if the path would
+    /// leave the root, or if a segment isn't a plain file name, such as
+    /// segments with separators, NUL bytes or Windows drive prefixes.
+    pub(crate) fn parse(path: &str) -> Option<RequestPath> {
+        let mut segments: Vec<String> = Vec::new();
+        let mut trailing_slash = true;
+
+        for raw in path.split('/') {
+            let segment = percent_decode_str(raw).decode_utf8().ok()?;
+            match &*segment {
+                "" => {}
+                "." => trailing_slash = true,
+                ".." => {
+                    segments.pop()?;
+                    trailing_slash = true;
+                }
+                segment => {
+                    if segment.contains(&['/', '\\', '\0'][..]) || !is_file_name(segment) {
+                        return None;
+                    }
+                    segments.push(segment.to_string());
+                    trailing_slash = false;
+                }
+            }
+        }
+        trailing_slash |= path.ends_with('/');
+
+        Some(RequestPath {
+            segments,
+            trailing_slash,
+        })
+    }
+
+    /// Whether this is the root directory.
+    pub(crate) fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Whether the request path ended with a slash, or an equivalent `.` or
+    /// `..` segment.
+    pub(crate) fn has_trailing_slash(&self) -> bool {
+        self.trailing_slash
+    }
+
+    /// Whether any component is a hidden file or directory.
+    pub(crate) fn is_hidden(&self) -> bool {
+        self.segments.iter().any(|segment| segment.starts_with('.'))
+    }
+
+    /// The normalized path without leading or trailing slash, such as
+    /// `assets/app.js`.
+    pub(crate) fn relative(&self) -> String {
+        self.segments.join("/")
+    }
+
+    /// The path of this file below `root`.
+    pub(crate) fn join_to(&self, root: &Path) -> PathBuf {
+        let mut path = root.to_path_buf();
+        path.extend(&self.segments);
+        path
+    }
+
+    /// The normalized path, with a leading slash and, for directories, a
+    /// trailing slash.
+    pub(crate) fn display(&self) -> String {
+        let mut path = String::from("/");
+        for segment in &self.segments {
+            path.push_str(segment);
+            path.push('/');
+        }
+        if !self.trailing_slash && !self.segments.is_empty() {
+            path.pop();
+        }
+        path
+    }
+}
+
+/// Whether the segment is a single, normal path component on this platform.
+fn is_file_name(segment: &str) -> bool {
+    let mut components = Path::new(segment).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
+/// The canonical path of `root` if `policy` needs it, resolved once when the
+/// policy is set.
+pub(crate) fn canonical_root(policy: Symlinks, root: &Path) -> Option<PathBuf> {
+    match policy {
+        Symlinks::WithinRoot => fs::canonicalize(root).ok(),
+        Symlinks::Follow | Symlinks::Deny => None,
+    }
+}
+
+/// Whether `path`, a path below `root`, may be served under the symlink
+/// policy.
+///
+/// `canonical_root` is the result of `canonical_root`. If the root couldn't
+/// be resolved then, such as when it didn't exist yet, it is resolved again.
+pub(crate) fn symlinks_allowed(
+    policy: Symlinks,
+    root: &Path,
+    canonical_root: Option<&Path>,
+    path: &Path,
+) -> io::Result<bool> {
+    match policy {
+        Symlinks::Follow => Ok(true),
+        Symlinks::WithinRoot => {
+            let path = fs::canonicalize(path)?;
+            match canonical_root {
+                Some(root) => Ok(path.starts_with(root)),
+                None => Ok(path.starts_with(fs::canonicalize(root)?)),
+            }
+        }
+        Symlinks::Deny => {
+            let relative = match path.strip_prefix(root) {
+                Ok(relative) => relative,
+                Err(_) => return Ok(false),
+            };
+            let mut current = root.to_path_buf();
+            for component in relative.components() {
+                current.push(component);
+                if fs::symlink_metadata(&current)?.file_type().is_symlink() {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RequestPath;
+
+    fn display(path: &str) -> Option<String> {
+        RequestPath::parse(path).map(|path| path.display())
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(display("/").as_deref(), Some("/"));
+        assert_eq!(display("").as_deref(), Some("/"));
+        assert_eq!(display("/a/b").as_deref(), Some("/a/b"));
+        assert_eq!(display("a/b/").as_deref(), Some("/a/b/"));
+        assert_eq!(display("//a/./b//").as_deref(), Some("/a/b/"));
+        assert_eq!(display("/a/../b").as_deref(), Some("/b"));
+        assert_eq!(display("/a/b/..").as_deref(), Some("/a/"));
+        assert_eq!(display("/a/.").as_deref(), Some("/a/"));
+        assert_eq!(display("/foo..bar.txt").as_deref(), Some("/foo..bar.txt"));
+    }
+
+    #[test]
+    fn percent_decode() {
+        assert_eq!(display("/a%20b").as_deref(), Some("/a b"));
+        assert_eq!(display("/%F0%9F%8E%89").as_deref(), Some("/🎉"));
+        assert_eq!(display("/a/%2e%2e/b").as_deref(), Some("/b"));
+        assert_eq!(display("/100%").as_deref(), Some("/100%"));
+    }
+
+    #[test]
+    fn reject() {
+        assert_eq!(display("/.."), None);
+        assert_eq!(display("/a/../.."), None);
+        assert_eq!(display("/%2e%2e/etc/passwd"), None);
+        assert_eq!(display("/a%2f..%2f..%2fetc"), None);
+        assert_eq!(display("/..%5cetc"), None);
+        assert_eq!(display("/a\\b"), None);
+        assert_eq!(display("/a%00.txt"), None);
+        assert_eq!(display("/%ff"), None);
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn reject_windows_prefix() {
+        assert_eq!(display("/C:"), None);
+        assert_eq!(display("/C:%5cWindows"), None);
+    }
+}
@@ -0,0 +1,339 @@
+//! Support for byte range requests (RFC 7233).
+
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use conduit::header::{self, HeaderMap, HeaderValue};
+use conduit::{box_error, Body, HandlerResult, Method, RequestExt, Response, StatusCode};
+use conduit_httpdate::parse_http_date;
+
+/// Requests asking for more ranges than this are served in full, to avoid
+/// spending resources on pathological requests.
+const MAX_RANGES: usize = 32;
+
+/// Turn a full `200 OK` response into a `206 Partial Content` or
+/// `416 Range Not Satisfiable` response if the request has a `Range` header
+/// that applies to it.
+pub(crate) fn respond(request: &dyn RequestExt, response: Response<Body>) -> HandlerResult {
+    if *request.method() != Method::GET || response.status() != StatusCode::OK {
+        return Ok(response);
+    }
+    let range = match request.headers().get(header::RANGE) {
+        Some(range) => range,
+        None => return Ok(response),
+    };
+    if !if_range_matches(request.headers(), response.headers()) {
+        return Ok(response);
+    }
+    let length = match content_length(response.headers()) {
+        Some(length) => length,
+        None => return Ok(response),
+    };
+    let ranges = match range.to_str().ok().and_then(|r| parse(r, length)) {
+        Some(ranges) => ranges,
+        None => return Ok(response),
+    };
+
+    if ranges.is_empty() {
+        return not_satisfiable(length);
+    }
+
+    let (mut parts, body) = response.into_parts();
+    if let Body::Stream(_) = body {
+        // Streams can't be seeked, so there is no way to serve a part of them
+        return Ok(Response::from_parts(parts, body));
+    }
+
+    parts.status = StatusCode::PARTIAL_CONTENT;
+    let headers = &mut parts.headers;
+
+    if let [range] = &ranges[..] {
+        headers.insert(header::CONTENT_RANGE, content_range(range, length));
+        headers.insert(header::CONTENT_LENGTH, (range.end - range.start).into());
+        let body = slice(body, range.clone()).map_err(box_error)?;
+        return Ok(Response::from_parts(parts, body));
+    }
+
+    let boundary = boundary();
+    let content_type = headers.remove(header::CONTENT_TYPE);
+    let mut segments = Vec::with_capacity(ranges.len() * 2 + 1);
+    for range in &ranges {
+        let mut part = format!("\r\n--{}\r\n", boundary).into_bytes();
+        if let Some(content_type) = &content_type {
+            part.extend_from_slice(b"Content-Type: ");
+            part.extend_from_slice(content_type.as_bytes());
+            part.extend_from_slice(b"\r\n");
+        }
+        part.extend_from_slice(b"Content-Range: ");
+        part.extend_from_slice(content_range(range, length).as_bytes());
+        part.extend_from_slice(b"\r\n\r\n");
+        segments.push(Segment::Bytes(part));
+        segments.push(Segment::Range(range.clone()));
+    }
+    segments.push(Segment::Bytes(
+        format!("\r\n--{}--\r\n", boundary).into_bytes(),
+    ));
+
+    let multipart_length: u64 = segments.iter().map(Segment::len).sum();
+    headers.insert(header::CONTENT_LENGTH, multipart_length.into());
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(&format!("multipart/byteranges; boundary={}", boundary)).unwrap(),
+    );
+
+    let body = match body {
+        Body::File(file) => Body::from_reader(Multipart::new(file, segments)),
+        Body::Static(bytes) => multipart_vec(Cursor::new(bytes), segments)?,
+        Body::Owned(bytes) => multipart_vec(Cursor::new(bytes), segments)?,
+        Body::Stream(_) => unreachable!(),
+    };
+    Ok(Response::from_parts(parts, body))
+}
+
+/// Whether the `If-Range` precondition, if any, allows a partial response.
+fn if_range_matches(request: &HeaderMap, response: &HeaderMap) -> bool {
+    let if_range = match request.get(header::IF_RANGE) {
+        Some(value) => value.as_bytes(),
+        None => return true,
+    };
+
+    if if_range.starts_with(b"\"") {
+        // Entity tags must be compared with the strong comparison function
+        response.get(header::ETAG).map(HeaderValue::as_bytes) == Some(if_range)
+    } else if if_range.starts_with(b"W/") {
+        false
+    } else {
+        // A date only matches if it is the `Last-Modified` date
+        let date = |value: &HeaderValue| parse_http_date(value.to_str().ok()?).ok();
+        let last_modified = response.get(header::LAST_MODIFIED).and_then(date);
+        last_modified.is_some() && last_modified == date(&request[header::IF_RANGE])
+    }
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Parse a `Range` header into the satisfiable ranges of a representation
+/// with `length` bytes.
+///
+/// Returns `None` if the header is malformed, uses a unit other than `bytes`,
+/// or asks for too many ranges, in which case it must be ignored. An empty
+/// list means none of the ranges are satisfiable.
+pub(crate) fn parse(header: &str, length: u64) -> Option<Vec<Range<u64>>> {
+    let (unit, specs) = header.split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return None;
+    }
+
+    let mut ranges = Vec::new();
+    let mut specs = specs
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .peekable();
+    // At least one range is required, so `bytes=` is malformed
+    specs.peek()?;
+    for spec in specs {
+        if ranges.len() == MAX_RANGES {
+            return None;
+        }
+
+        let (first, last) = spec.split_once('-')?;
+        let (first, last) = (first.trim(), last.trim());
+        let range = if first.is_empty() {
+            let suffix: u64 = last.parse().ok()?;
+            length.saturating_sub(suffix)..length
+        } else {
+            let first: u64 = first.parse().ok()?;
+            let end = if last.is_empty() {
+                length
+            } else {
+                let last: u64 = last.parse().ok()?;
+                if last < first {
+                    return None;
+                }
+                last.saturating_add(1).min(length)
+            };
+            first..end
+        };
+
+        if range.start < range.end {
+            ranges.push(range);
+        }
+    }
+
+    Some(ranges)
+}
+
+fn content_range(range: &Range<u64>, length: u64) -> HeaderValue {
+    let value = format!("bytes {}-{}/{}", range.start, range.end - 1, length);
+    HeaderValue::from_str(&value).unwrap()
+}
+
+fn not_satisfiable(length: u64) -> HandlerResult {
+    Response::builder()
+        .status(StatusCode::RANGE_NOT_SATISFIABLE)
+        .header(header::CONTENT_RANGE, format!("bytes */{}", length))
+        .header(header::CONTENT_LENGTH, 0)
+        .body(Body::empty())
+        .map_err(box_error)
+}
+
+/// Restrict a body to a single range of bytes.
+fn slice(body: Body, range: Range<u64>) -> io::Result<Body> {
+    let (start, end) = (range.start as usize, range.end as usize);
+    Ok(match body {
+        Body::Static(bytes) => Body::Static(&bytes[start..end]),
+        Body::Owned(mut bytes) => {
+            bytes.truncate(end);
+            bytes.drain(..start);
+            Body::Owned(bytes)
+        }
+        Body::File(mut file) => {
+            file.seek(SeekFrom::Start(range.start))?;
+            Body::from_reader(file.take(range.end - range.start))
+        }
+        body @ Body::Stream(_) => body,
+    })
+}
+
+/// Generate a boundary that is unlikely to appear in the content.
+fn boundary() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default();
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed) as u64;
+    format!("{:016x}{:08x}", nanos, count)
+}
+
+/// A piece of a `multipart/byteranges` body.
+enum Segment {
+    /// The delimiter and headers of a part, or the final delimiter.
+    Bytes(Vec<u8>),
+    /// A range of bytes of the representation.
+    Range(Range<u64>),
+}
+
+impl Segment {
+    fn len(&self) -> u64 {
+        match self {
+            Segment::Bytes(bytes) => bytes.len() as u64,
+            Segment::Range(range) => range.end - range.start,
+        }
+    }
+}
+
+fn multipart_vec<R: Read + Seek>(
+    source: R,
+    segments: Vec<Segment>,
+) -> Result<Body, conduit::BoxError> {
+    let mut bytes = Vec::new();
+    Multipart::new(source, segments)
+        .read_to_end(&mut bytes)
+        .map_err(box_error)?;
+    Ok(Body::from_vec(bytes))
+}
+
+/// A reader producing a `multipart/byteranges` body, seeking the source
+/// lazily as each part is reached.
+struct Multipart<R> {
+    source: R,
+    segments: std::vec::IntoIter<Segment>,
+    current: Option<Cursor<Vec<u8>>>,
+    remaining: u64,
+}
+
+impl<R: Read + Seek> Multipart<R> {
+    fn new(source: R, segments: Vec<Segment>) -> Self {
+        Multipart {
+            source,
+            segments: segments.into_iter(),
+            current: None,
+            remaining: 0,
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for Multipart<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(current) = &mut self.current {
+                let read = current.read(buf)?;
+                if read > 0 || buf.is_empty() {
+                    return Ok(read);
+                }
+                self.current = None;
+            }
+
+            if self.remaining > 0 {
+                let max = buf.len().min(self.remaining as usize);
+                let read = self.source.read(&mut buf[..max])?;
+                if read == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                self.remaining -= read as u64;
+                return Ok(read);
+            }
+
+            match self.segments.next() {
+                Some(Segment::Bytes(bytes)) => self.current = Some(Cursor::new(bytes)),
+                Some(Segment::Range(range)) => {
+                    self.source.seek(SeekFrom::Start(range.start))?;
+                    self.remaining = range.end - range.start;
+                }
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    fn parse(header: &str, length: u64) -> Option<Vec<(u64, u64)>> {
+        let ranges = super::parse(header, length)?;
+        Some(ranges.into_iter().map(|r| (r.start, r.end)).collect())
+    }
+
+    #[test]
+    fn parse_ranges() {
+        assert_eq!(parse("bytes=0-499", 1000), Some(vec![(0, 500)]));
+        assert_eq!(parse("bytes=500-", 1000), Some(vec![(500, 1000)]));
+        assert_eq!(parse("bytes=-200", 1000), Some(vec![(800, 1000)]));
+        assert_eq!(parse("bytes=-2000", 1000), Some(vec![(0, 1000)]));
+        assert_eq!(parse("bytes=900-1999", 1000), Some(vec![(900, 1000)]));
+        assert_eq!(
+            parse("bytes= 0-0 , -1", 1000),
+            Some(vec![(0, 1), (999, 1000)])
+        );
+    }
+
+    #[test]
+    fn parse_unsatisfiable() {
+        assert_eq!(parse("bytes=1000-", 1000), Some(vec![]));
+        assert_eq!(parse("bytes=-0", 1000), Some(vec![]));
+        assert_eq!(parse("bytes=0-10", 0), Some(vec![]));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(parse("items=0-10", 1000), None);
+        assert_eq!(parse("bytes=10-5", 1000), None);
+        assert_eq!(parse("bytes=abc", 1000), None);
+        assert_eq!(parse("bytes=1-x", 1000), None);
+        assert_eq!(parse("0-10", 1000), None);
+        assert_eq!(parse("bytes=", 1000), None);
+        assert_eq!(parse("bytes= , ", 1000), None);
+        assert_eq!(parse(&format!("bytes={}", "0-0,".repeat(33)), 1000), None);
+    }
+}
@@ -16,11 +16,12 @@ impl ResponseExt for Response<Body> {
     ///
     /// # Blocking
     ///
-    /// This function may block if the value is a `Body::File`.
+    /// This function may block if the value is a `Body::File` or `Body::Stream`.
     ///
     /// # Panics
     ///
-    /// This function panics if there is an error reading a `Body::File`.
+    /// This function panics if there is an error reading a `Body::File` or
+    /// `Body::Stream`.
     fn into_cow(self) -> Cow<'static, [u8]> {
         use conduit::Body::*;
 
@@ -32,6 +33,11 @@ impl ResponseExt for Response<Body> {
                 std::io::copy(&mut file, &mut vec).unwrap();
                 vec.into()
             }
+            Stream(mut reader) => {
+                let mut vec = Vec::new();
+                std::io::copy(&mut reader, &mut vec).unwrap();
+                vec.into()
+            }
         }
     }
 }
@@ -154,11 +160,12 @@ impl conduit::RequestExt for MockRequest {
 
 #[cfg(test)]
 mod tests {
-    use super::MockRequest;
+    use super::{MockRequest, ResponseExt};
 
+    use std::io::Cursor;
     use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
 
-    use conduit::{header, Host, Method, RequestExt, Scheme, Version};
+    use conduit::{header, Body, Host, Method, RequestExt, Response, Scheme, Version};
 
     #[test]
     fn simple_request_test() {
@@ -213,4 +220,12 @@ mod tests {
         assert_eq!(req.headers().get(header::USER_AGENT).unwrap(), "lulz");
         assert_eq!(req.headers().get(header::DNT).unwrap(), "1");
     }
+
+    #[test]
+    fn stream_into_cow() {
+        let reader = Cursor::new(b"Hello world".to_vec());
+        let res = Response::new(Body::from_reader(reader));
+
+        assert_eq!(*res.into_cow(), b"Hello world"[..]);
+    }
 }
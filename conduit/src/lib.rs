@@ -35,6 +35,11 @@ pub enum Body {
     Static(&'static [u8]),
     Owned(Vec<u8>),
     File(File),
+    /// A body of unknown length that is read incrementally by the server.
+    ///
+    /// Servers may use chunked transfer encoding when the handler does not
+    /// provide a `Content-Length` header.
+    Stream(Box<dyn Read + Send>),
 }
 
 impl Body {
@@ -52,6 +57,15 @@ impl Body {
     pub fn from_vec(bytes: Vec<u8>) -> Self {
         Self::Owned(bytes)
     }
+
+    /// Create a new streaming `Body` from the provided reader.
+    ///
+    /// # Blocking
+    ///
+    /// The reader is driven by the server using the blocking `Read` API.
+    pub fn from_reader<R: Read + Send + 'static>(reader: R) -> Self {
+        Self::Stream(Box::new(reader))
+    }
 }
 
 /// A helper to convert a concrete error type into a `Box<dyn Error + Send>`
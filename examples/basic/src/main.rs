@@ -1,6 +1,6 @@
-use civet::{Config, Server};
 use conduit::{Body, HttpResult, RequestExt, Response};
 use conduit_router::{RequestParams, RouteBuilder};
+use conduit_server::{Config, Server};
 use std::sync::mpsc::channel;
 
 fn name(req: &mut dyn RequestExt) -> HttpResult {
@@ -21,7 +21,7 @@ fn main() {
 
     let mut cfg = Config::new();
     cfg.port(8888).threads(1);
-    let _server = Server::start(cfg, router);
+    let _server = Server::start(cfg, router).unwrap();
 
     // Preventing process exit.
     let (_tx, rx) = channel::<()>();